@@ -0,0 +1,102 @@
+// Run once on databases created before orders carried tenant_id, and before setting
+// MULTI_TENANT=true:
+//   TENANT_ID=<tenant> mongosh "$MONGODB_URI" apps/server/migrations/multi-tenant.js
+//
+// Every ownership filter now matches tenant_id as well as user_id (null outside
+// MULTI_TENANT). This script:
+// - stamps TENANT_ID, the value existing users' tokens carry in TENANT_CLAIM (by default
+//   the issuer URL), on orders, tombstones and uploaded images that have no tenant yet.
+//   Leave it unset on single-tenant deployments: their data stays tenant-less.
+// - with SPLIT_TENANT_PREFIX=1, instead splits owner keys written as `<tenant>|<sub>` by
+//   earlier MULTI_TENANT builds into tenant_id and user_id. Do not use it otherwise:
+//   some providers' subjects contain `|` themselves.
+// - replaces the user_id indexes with ones that lead with tenant_id.
+
+db = db.getSiblingDB('order_wizard');
+
+const tenantId = process.env.TENANT_ID;
+const splitPrefix = process.env.SPLIT_TENANT_PREFIX === '1';
+const images = db.getCollection('order_images.files');
+
+if (tenantId && splitPrefix) {
+  throw new Error('Set either TENANT_ID or SPLIT_TENANT_PREFIX, not both');
+}
+
+if (tenantId) {
+  for (const collection of [db.orders, db.order_tombstones]) {
+    const result = collection.updateMany({ tenant_id: null }, { $set: { tenant_id: tenantId } });
+    print(`${collection.getName()}: ${result.modifiedCount} documents assigned to ${tenantId}`);
+  }
+  const result = images.updateMany(
+    { 'metadata.tenant_id': null },
+    { $set: { 'metadata.tenant_id': tenantId } }
+  );
+  print(`images: ${result.modifiedCount} files assigned to ${tenantId}`);
+}
+
+if (splitPrefix) {
+  const split = (field) => {
+    const separator = { $indexOfBytes: [`$${field}`, '|'] };
+    return [
+      {
+        $set: {
+          [field.replace('user_id', 'tenant_id')]: { $substrBytes: [`$${field}`, 0, separator] },
+          [field]: { $substrBytes: [`$${field}`, { $add: [separator, 1] }, -1] },
+        },
+      },
+    ];
+  };
+  for (const collection of [db.orders, db.order_tombstones]) {
+    const result = collection.updateMany({ tenant_id: null, user_id: /\|/ }, split('user_id'));
+    print(`${collection.getName()}: ${result.modifiedCount} owner keys split`);
+  }
+  const result = images.updateMany(
+    { 'metadata.tenant_id': null, 'metadata.user_id': /\|/ },
+    split('metadata.user_id')
+  );
+  print(`images: ${result.modifiedCount} owner keys split`);
+}
+
+const dropIndex = (collection, name) => {
+  if (collection.getIndexes().some((index) => index.name === name)) {
+    collection.dropIndex(name);
+  }
+};
+
+// Keep the per-source unique key if order-source-unique-index.js was run
+const perSource = db.orders.getIndexes().some((index) => index.name === 'idx_user_source_order_unique');
+if (perSource) {
+  db.orders.createIndex(
+    { tenant_id: 1, user_id: 1, source: 1, order_number: 1 },
+    { unique: true, name: 'idx_tenant_user_source_order_unique' }
+  );
+} else {
+  db.orders.createIndex(
+    { tenant_id: 1, user_id: 1, order_number: 1 },
+    { unique: true, name: 'idx_tenant_user_order_unique' }
+  );
+}
+db.orders.createIndex({ tenant_id: 1, user_id: 1 }, { name: 'idx_tenant_user' });
+db.orders.createIndex({ id: 1, tenant_id: 1, user_id: 1 }, { name: 'idx_id_tenant_user' });
+db.orders.createIndex({ tenant_id: 1, user_id: 1, order_date_iso: 1 }, { name: 'idx_tenant_user_order_date' });
+db.order_tombstones.createIndex(
+  { tenant_id: 1, user_id: 1, id: 1 },
+  { unique: true, name: 'idx_tombstone_tenant_user_id' }
+);
+images.createIndex(
+  { 'metadata.tenant_id': 1, 'metadata.user_id': 1, filename: 1 },
+  { name: 'idx_image_tenant_user_filename' }
+);
+
+for (const name of [
+  'idx_user_id',
+  'idx_user_order_unique',
+  'idx_user_source_order_unique',
+  'idx_id_user',
+  'idx_user_order_date',
+]) {
+  dropIndex(db.orders, name);
+}
+dropIndex(db.order_tombstones, 'idx_tombstone_user_id');
+
+print('orders, tombstones and images are now indexed by (tenant_id, user_id)');
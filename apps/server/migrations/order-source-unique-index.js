@@ -0,0 +1,23 @@
+// Run once before setting ORDER_SOURCES=true:
+//   mongosh "$MONGODB_URI" apps/server/migrations/order-source-unique-index.js
+//
+// Replaces the (tenant_id, user_id, order_number) unique index with
+// (tenant_id, user_id, source, order_number), so the same order number can be stored once
+// per source. Existing orders need no rewrite: they have no source, which the index and the
+// upsert filter treat as null. Reverting means merging any per-source duplicates first, then
+// restoring the old index. Run multi-tenant.js first on databases created before tenant_id.
+
+db = db.getSiblingDB('order_wizard');
+
+db.orders.createIndex(
+  { tenant_id: 1, user_id: 1, source: 1, order_number: 1 },
+  { unique: true, name: 'idx_tenant_user_source_order_unique' }
+);
+
+for (const name of ['idx_tenant_user_order_unique', 'idx_user_order_unique']) {
+  if (db.orders.getIndexes().some((index) => index.name === name)) {
+    db.orders.dropIndex(name);
+  }
+}
+
+print('orders unique index now covers (tenant_id, user_id, source, order_number)');
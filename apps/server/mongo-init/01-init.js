@@ -3,17 +3,40 @@
 
 db = db.getSiblingDB('order_wizard');
 
-// Create indexes
-db.orders.createIndex({ user_id: 1 }, { name: 'idx_user_id' });
+// Create indexes. Every ownership filter matches tenant_id (null outside MULTI_TENANT)
+// and user_id, so both lead each index.
+db.orders.createIndex({ tenant_id: 1, user_id: 1 }, { name: 'idx_tenant_user' });
 
 db.orders.createIndex(
-  { user_id: 1, order_number: 1 },
-  { unique: true, name: 'idx_user_order_unique' }
+  { tenant_id: 1, user_id: 1, order_number: 1 },
+  { unique: true, name: 'idx_tenant_user_order_unique' }
 );
 
 db.orders.createIndex(
-  { id: 1, user_id: 1 },
-  { name: 'idx_id_user' }
+  { id: 1, tenant_id: 1, user_id: 1 },
+  { name: 'idx_id_tenant_user' }
+);
+
+db.orders.createIndex(
+  { tenant_id: 1, user_id: 1, order_date_iso: 1 },
+  { name: 'idx_tenant_user_order_date' }
+);
+
+// Tombstones for hard-deleted orders; kept 90 days so stale ids get 410 Gone
+db.order_tombstones.createIndex(
+  { tenant_id: 1, user_id: 1, id: 1 },
+  { unique: true, name: 'idx_tombstone_tenant_user_id' }
+);
+
+db.order_tombstones.createIndex(
+  { purged_at: 1 },
+  { expireAfterSeconds: 90 * 24 * 60 * 60, name: 'idx_tombstone_ttl' }
+);
+
+// Uploaded product images, looked up by order id within the owner's files
+db.getCollection('order_images.files').createIndex(
+  { 'metadata.tenant_id': 1, 'metadata.user_id': 1, filename: 1 },
+  { name: 'idx_image_tenant_user_filename' }
 );
 
 print('Indexes created for orders collection');
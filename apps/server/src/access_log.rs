@@ -0,0 +1,56 @@
+use axum::{body::Body, extract::Request, response::Response};
+use std::time::Duration;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::TraceLayer,
+};
+use tracing::Span;
+
+use crate::config::get_config;
+
+/// Tracing target for the access log (`RUST_LOG=order_wizard::access=info`)
+const TARGET: &str = "order_wizard::access";
+
+pub type AccessLogLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    fn(&Request) -> Span,
+    (),
+    fn(&Response, Duration, &Span),
+    (),
+    (),
+    (),
+>;
+
+/// One line per request with method, path, status and latency. Paths listed in
+/// `ACCESS_LOG_EXCLUDE` get a disabled span and are not logged. Each request runs in a
+/// `request` span, so anything the handler logs carries the method and path too.
+pub fn access_log_layer() -> AccessLogLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as fn(&Request) -> Span)
+        .on_request(())
+        .on_response(log_response as fn(&Response, Duration, &Span))
+        .on_body_chunk(())
+        .on_eos(())
+        .on_failure(())
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let path = request.uri().path();
+    if get_config().access_log_exclude.iter().any(|excluded| excluded == path) {
+        return Span::none();
+    }
+    tracing::info_span!(target: TARGET, "request", method = %request.method(), path = %path)
+}
+
+fn log_response(response: &Response<Body>, latency: Duration, span: &Span) {
+    if span.is_disabled() {
+        return;
+    }
+    let status = response.status().as_u16();
+    let latency_ms = latency.as_secs_f64() * 1000.0;
+    if response.status().is_server_error() {
+        tracing::warn!(target: TARGET, parent: span, status, latency_ms, "request failed");
+    } else {
+        tracing::info!(target: TARGET, parent: span, status, latency_ms, "request completed");
+    }
+}
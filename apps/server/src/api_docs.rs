@@ -0,0 +1,59 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use utoipa::openapi::OpenApi;
+
+/// Where the OpenAPI document is served; Swagger UI loads it from here
+pub const SPEC_PATH: &str = "/api-docs/openapi.json";
+
+/// The document only changes between builds: reuse it briefly, then revalidate by ETag
+const CACHE_CONTROL: HeaderValue = HeaderValue::from_static("public, max-age=300");
+
+/// The serialized document and its ETag, both computed once at startup
+struct Spec {
+    body: Bytes,
+    etag: HeaderValue,
+}
+
+/// Serve the OpenAPI document with an ETag and `Cache-Control`, answering a matching
+/// `If-None-Match` with 304 so Swagger UI does not download it on every load
+pub fn spec_router(api: &OpenApi) -> Router {
+    let body = api.to_json().expect("OpenAPI document serializes to JSON");
+    let digest = Sha256::digest(body.as_bytes());
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    let etag = HeaderValue::from_str(&format!("\"{}\"", hex)).expect("hex ETag is a valid header value");
+
+    Router::new()
+        .route(SPEC_PATH, get(serve_spec))
+        .with_state(Arc::new(Spec { body: Bytes::from(body), etag }))
+}
+
+async fn serve_spec(State(spec): State<Arc<Spec>>, headers: HeaderMap) -> Response {
+    let etag = spec.etag.to_str().unwrap_or_default();
+    // Weak comparison, as If-None-Match requires: a `W/` prefix still matches
+    let not_modified = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag);
+
+    let cache_headers = [(header::ETAG, spec.etag.clone()), (header::CACHE_CONTROL, CACHE_CONTROL)];
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        cache_headers,
+        [(header::CONTENT_TYPE, HeaderValue::from_static("application/json"))],
+        spec.body.clone(),
+    )
+        .into_response()
+}
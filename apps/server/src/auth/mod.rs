@@ -7,11 +7,21 @@ use axum::{
     Json,
 };
 use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use mongodb::bson::{doc, Document};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::sync::RwLock;
 use utoipa::ToSchema;
 
+use crate::config::{get_config, Config};
+
 /// JWKS (JSON Web Key Set) structure from Cognito
 #[derive(Debug, Deserialize)]
 pub struct Jwks {
@@ -29,10 +39,17 @@ pub struct Jwk {
     pub e: String,
 }
 
+/// Tracing target for token verification (`RUST_LOG=order_wizard::auth=debug`)
+const TARGET: &str = "order_wizard::auth";
+
+/// How long fetched signing keys are trusted before refetching
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(3600);
+
 /// Cached JWKS with expiry tracking
 struct JwksCache {
     keys: HashMap<String, DecodingKey>,
-    fetched_at: std::time::Instant,
+    /// `JWKS_CACHE_TTL` after the keys were fetched
+    fresh_until: Instant,
 }
 
 /// Global JWKS verifier
@@ -41,6 +58,8 @@ static JWKS_VERIFIER: std::sync::OnceLock<JwksVerifier> = std::sync::OnceLock::n
 /// JWT verifier with JWKS caching
 pub struct JwksVerifier {
     cache: Arc<RwLock<Option<JwksCache>>>,
+    /// Set while a background refresh of a stale cache is in flight
+    refreshing: AtomicBool,
     jwks_url: String,
     issuer: String,
     client_id: String,
@@ -52,6 +71,7 @@ impl JwksVerifier {
         let jwks_url = format!("{}/.well-known/jwks.json", issuer);
         let verifier = Self {
             cache: Arc::new(RwLock::new(None)),
+            refreshing: AtomicBool::new(false),
             jwks_url,
             issuer,
             client_id,
@@ -63,6 +83,27 @@ impl JwksVerifier {
         JWKS_VERIFIER.get()
     }
 
+    /// Whether tokens can currently be verified: true while the cached JWKS is fresh,
+    /// otherwise refetches it (so probes only hit Cognito once per cache lifetime)
+    pub async fn keys_available() -> bool {
+        let Some(verifier) = Self::get() else {
+            return false;
+        };
+        {
+            let cache = verifier.cache.read().await;
+            if cache.as_ref().is_some_and(|c| Instant::now() < c.fresh_until) {
+                return true;
+            }
+        }
+        match verifier.refresh().await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!(target: TARGET, "JWKS unavailable: {}", e);
+                false
+            }
+        }
+    }
+
     /// Fetch JWKS from Cognito and cache the keys
     async fn fetch_jwks(&self) -> Result<HashMap<String, DecodingKey>, String> {
         let response = reqwest::get(&self.jwks_url)
@@ -86,54 +127,93 @@ impl JwksVerifier {
         Ok(keys)
     }
 
-    /// Get decoding key for a given kid, fetching JWKS if needed
-    async fn get_key(&self, kid: &str) -> Result<DecodingKey, String> {
+    /// Fetch JWKS and replace the cache
+    async fn refresh(&self) -> Result<HashMap<String, DecodingKey>, String> {
+        let keys = self.fetch_jwks().await?;
+
+        let mut cache = self.cache.write().await;
+        *cache = Some(JwksCache {
+            keys: keys.clone(),
+            fresh_until: Instant::now() + JWKS_CACHE_TTL,
+        });
+
+        Ok(keys)
+    }
+
+    /// Refetch the JWKS without making the caller wait; at most one refresh runs at a time
+    fn refresh_in_background(&'static self) {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = self.refresh().await {
+                tracing::warn!(target: TARGET, "Background JWKS refresh failed: {}", e);
+            }
+            self.refreshing.store(false, Ordering::Release);
+        });
+    }
+
+    /// Get decoding key for a given kid, fetching JWKS if needed. A known key in a cache
+    /// that is past its TTL but within `jwks_max_stale` is returned immediately while the
+    /// JWKS is refreshed in the background.
+    async fn get_key(&'static self, kid: &str) -> Result<DecodingKey, String> {
         // Check cache first
         {
             let cache = self.cache.read().await;
             if let Some(ref cached) = *cache {
-                // Cache is valid for 1 hour
-                if cached.fetched_at.elapsed() < Duration::from_secs(3600) {
+                let now = Instant::now();
+                if now < cached.fresh_until + get_config().jwks_max_stale {
                     if let Some(key) = cached.keys.get(kid) {
+                        if now >= cached.fresh_until {
+                            self.refresh_in_background();
+                        }
                         return Ok(key.clone());
                     }
                 }
             }
         }
 
-        // Fetch fresh JWKS
-        let keys = self.fetch_jwks().await?;
-
-        // Update cache
-        {
-            let mut cache = self.cache.write().await;
-            *cache = Some(JwksCache {
-                keys: keys.clone(),
-                fetched_at: std::time::Instant::now(),
-            });
-        }
+        let keys = self.refresh().await?;
 
         keys.get(kid)
             .cloned()
             .ok_or_else(|| "Key not found in JWKS".to_string())
     }
 
+    /// Claims of a valid token, or `None` when it is invalid or auth is not configured
+    pub async fn verify(token: &str) -> Option<Claims> {
+        let verifier = Self::get()?;
+        match verifier.verify_token(token).await {
+            Ok(claims) => Some(claims),
+            Err(e) => {
+                tracing::debug!(target: TARGET, "Token is not active: {}", e);
+                None
+            }
+        }
+    }
+
     /// Verify and decode a JWT token
-    async fn verify_token(&self, token: &str) -> Result<Claims, &'static str> {
+    async fn verify_token(&'static self, token: &str) -> Result<Claims, &'static str> {
         // Decode header to get kid
         let header = decode_header(token).map_err(|e| {
-            tracing::debug!("Invalid token header: {}", e);
+            tracing::debug!(target: TARGET, "Invalid token header: {}", e);
             "Invalid token"
         })?;
 
+        // Never let the token pick its own algorithm
+        if !get_config().jwt_algorithms.contains(&header.alg) {
+            tracing::debug!(target: TARGET, "Token alg {:?} is not allowed", header.alg);
+            return Err("Invalid token");
+        }
+
         let kid = header.kid.ok_or_else(|| {
-            tracing::debug!("Token missing kid claim");
+            tracing::debug!(target: TARGET, "Token missing kid claim");
             "Invalid token"
         })?;
 
         // Get the decoding key
         let key = self.get_key(&kid).await.map_err(|e| {
-            tracing::debug!("Failed to get key: {}", e);
+            tracing::debug!(target: TARGET, "Failed to get key: {}", e);
             "Invalid token"
         })?;
 
@@ -143,26 +223,211 @@ impl JwksVerifier {
         validation.set_audience(&[&self.client_id]);
 
         // Decode and verify
-        let token_data = decode::<Claims>(token, &key, &validation).map_err(|e| {
-            tracing::debug!("Token validation failed: {}", e);
+        let token_data = decode::<RawClaims>(token, &key, &validation).map_err(|e| {
+            tracing::debug!(target: TARGET, "Token validation failed: {}", e);
             "Invalid token"
         })?;
 
-        Ok(token_data.claims)
+        token_data.claims.into_claims(get_config()).ok_or_else(|| {
+            let config = get_config();
+            if config.multi_tenant {
+                tracing::debug!(
+                    target: TARGET,
+                    "Token missing {} claim or valid {} claim",
+                    config.identity_id_claim,
+                    config.tenant_claim
+                );
+            } else {
+                tracing::debug!(target: TARGET, "Token missing {} claim", config.identity_id_claim);
+            }
+            "Invalid token"
+        })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Verified identity of the caller. `sub` keys all of the user's data, within their
+/// tenant under `MULTI_TENANT`; it and the profile fields come from the claims named by
+/// `IDENTITY_*_CLAIM`.
+#[derive(Debug, Serialize, Clone)]
 pub struct Claims {
     pub sub: String,
+    /// Value of `TENANT_CLAIM`; `None` unless `MULTI_TENANT` is on
+    pub tenant_id: Option<String>,
     pub email: Option<String>,
-    #[serde(rename = "cognito:username")]
     pub username: Option<String>,
     pub iss: Option<String>,
-    pub aud: Option<String>,
+    pub aud: Vec<String>,
     pub exp: Option<u64>,
     pub iat: Option<u64>,
     pub token_use: Option<String>,
+    pub profile: UserProfile,
+}
+
+impl Claims {
+    /// Whose stored data this caller reads and writes
+    pub fn owner(&self) -> Owner {
+        Owner {
+            user_id: self.sub.clone(),
+            tenant_id: self.tenant_id.clone(),
+        }
+    }
+}
+
+/// Owner of stored orders, tombstones and images: the user, within their tenant under
+/// `MULTI_TENANT`. Both fields are stored on every document and every ownership filter
+/// is built by [`Owner::filter`], so one tenant's data never matches another's.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Owner {
+    pub user_id: String,
+    pub tenant_id: Option<String>,
+}
+
+impl Owner {
+    /// `conditions` narrowed to the owner's documents. Single-tenant owners match
+    /// `tenant_id: null`, i.e. documents stored without a tenant.
+    pub fn filter(&self, mut conditions: Document) -> Document {
+        conditions.insert("user_id", &self.user_id);
+        conditions.insert("tenant_id", &self.tenant_id);
+        conditions
+    }
+
+    /// The owner fields as stored on GridFS files, under `metadata`
+    pub fn metadata(&self) -> Document {
+        let mut metadata = doc! { "user_id": &self.user_id };
+        if let Some(tenant_id) = &self.tenant_id {
+            metadata.insert("tenant_id", tenant_id);
+        }
+        metadata
+    }
+
+    /// [`Owner::filter`] for GridFS files, whose owner fields sit under `metadata`
+    pub fn metadata_filter(&self, mut conditions: Document) -> Document {
+        conditions.insert("metadata.user_id", &self.user_id);
+        conditions.insert("metadata.tenant_id", &self.tenant_id);
+        conditions
+    }
+}
+
+#[cfg(test)]
+impl Claims {
+    /// Caller identity for handler tests, as single-tenant mode derives it
+    pub fn for_user(user_id: &str) -> Self {
+        Claims {
+            sub: user_id.to_string(),
+            tenant_id: None,
+            email: None,
+            username: None,
+            iss: None,
+            aud: Vec::new(),
+            exp: None,
+            iat: None,
+            token_use: None,
+            profile: UserProfile::default(),
+        }
+    }
+}
+
+/// Standard OIDC profile claims, read the same way whatever the provider, so `/me` has
+/// one shape. Each is `None` when the token does not carry it (access tokens often don't).
+#[derive(Debug, Default, Serialize, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    pub email_verified: Option<bool>,
+    /// Full display name
+    pub name: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    /// Avatar URL
+    pub picture: Option<String>,
+    /// BCP 47 language tag, e.g. `en-US`
+    pub locale: Option<String>,
+}
+
+/// Token payload as issued; identity claims vary by provider, so they stay untyped
+#[derive(Debug, Deserialize)]
+struct RawClaims {
+    iss: Option<String>,
+    #[serde(default, deserialize_with = "audiences")]
+    aud: Vec<String>,
+    exp: Option<u64>,
+    iat: Option<u64>,
+    token_use: Option<String>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `aud` is a single string for some providers and an array for others (Auth0 access
+/// tokens list the API and `/userinfo`); RFC 7519 allows both
+fn audiences<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(aud) => vec![aud],
+        Audience::Many(aud) => aud,
+    })
+}
+
+impl RawClaims {
+    /// Pick out the identity fields; `None` when the id claim (or, under `MULTI_TENANT`,
+    /// the tenant claim) is missing or empty
+    fn into_claims(self, config: &Config) -> Option<Claims> {
+        let profile = self.profile();
+        let sub = self.claim(&config.identity_id_claim)?;
+        let tenant_id = if config.multi_tenant {
+            Some(self.tenant(&config.tenant_claim)?)
+        } else {
+            None
+        };
+        Some(Claims {
+            sub,
+            tenant_id,
+            email: self.claim(&config.identity_email_claim),
+            username: self.claim(&config.identity_name_claim),
+            iss: self.iss,
+            aud: self.aud,
+            exp: self.exp,
+            iat: self.iat,
+            token_use: self.token_use,
+            profile,
+        })
+    }
+
+    fn profile(&self) -> UserProfile {
+        UserProfile {
+            // Some providers send the flag as the string "true"
+            email_verified: match self.other.get("email_verified") {
+                Some(serde_json::Value::Bool(verified)) => Some(*verified),
+                Some(serde_json::Value::String(verified)) => verified.parse().ok(),
+                _ => None,
+            },
+            name: self.claim("name"),
+            given_name: self.claim("given_name"),
+            family_name: self.claim("family_name"),
+            picture: self.claim("picture"),
+            locale: self.claim("locale"),
+        }
+    }
+
+    /// The tenant claim; `iss` is read from its own field
+    fn tenant(&self, name: &str) -> Option<String> {
+        match name {
+            "iss" => self.iss.clone().filter(|iss| !iss.is_empty()),
+            _ => self.claim(name),
+        }
+    }
+
+    /// A string claim, or a numeric one as its decimal text (some providers use numeric ids)
+    fn claim(&self, name: &str) -> Option<String> {
+        match self.other.get(name)? {
+            serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
 }
 
 /// OAuth 2.0 error response per RFC 6749 Section 5.2
@@ -185,6 +450,14 @@ impl AuthError {
         }
     }
 
+    /// Caller failed client authentication (RFC 6749 Section 5.2)
+    pub fn invalid_client(description: impl Into<String>) -> Self {
+        Self {
+            error: "invalid_client".to_string(),
+            error_description: Some(description.into()),
+        }
+    }
+
     fn invalid_request(description: impl Into<String>) -> Self {
         Self {
             error: "invalid_request".to_string(),
@@ -244,7 +517,7 @@ pub async fn auth_middleware(mut request: Request<Body>, next: Next) -> Response
     let claims = match verifier.verify_token(token).await {
         Ok(c) => c,
         Err(e) => {
-            tracing::warn!("Token verification failed: {}", e);
+            tracing::warn!(target: TARGET, "Token verification failed: {}", e);
             return AuthError::invalid_token(e).into_response();
         }
     };
@@ -280,3 +553,255 @@ where
             })
     }
 }
+
+/// Extractor for routes that work without a login: the verified bearer token's claims
+/// when one is sent, otherwise `None`. Invalid tokens are treated as anonymous.
+#[derive(Debug, Clone)]
+pub struct OptionalAuthUser(pub Option<Claims>);
+
+impl<S> axum::extract::FromRequestParts<S> for OptionalAuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        let claims = match (token, JwksVerifier::get()) {
+            (Some(token), Some(verifier)) => match verifier.verify_token(token).await {
+                Ok(claims) => Some(claims),
+                Err(e) => {
+                    tracing::debug!(target: TARGET, "Ignoring optional bearer token: {}", e);
+                    None
+                }
+            },
+            _ => None,
+        };
+
+        Ok(OptionalAuthUser(claims))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use serde_json::json;
+
+    fn raw_claims(claims: serde_json::Value) -> RawClaims {
+        serde_json::from_value(claims).unwrap()
+    }
+
+    fn multi_tenant_config() -> Config {
+        let mut config = test_config();
+        config.multi_tenant = true;
+        config
+    }
+
+    fn identity_config(id: &str, email: &str, name: &str) -> Config {
+        let mut config = test_config();
+        config.identity_id_claim = id.into();
+        config.identity_email_claim = email.into();
+        config.identity_name_claim = name.into();
+        config
+    }
+
+    #[test]
+    fn cognito_claims_use_the_defaults() {
+        let claims = raw_claims(json!({
+            "sub": "3f1c-uuid",
+            "email": "user@example.com",
+            "cognito:username": "user",
+            "email_verified": true,
+            "token_use": "id",
+        }))
+        .into_claims(&test_config())
+        .unwrap();
+        assert_eq!(claims.sub, "3f1c-uuid");
+        assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+        assert_eq!(claims.username.as_deref(), Some("user"));
+        assert_eq!(claims.token_use.as_deref(), Some("id"));
+        assert_eq!(claims.profile.email_verified, Some(true));
+    }
+
+    #[test]
+    fn auth0_claims_with_profile_fields() {
+        let claims = raw_claims(json!({
+            "sub": "auth0|abc123",
+            "email": "user@example.com",
+            "nickname": "user",
+            "name": "Ada Lovelace",
+            "picture": "https://example.com/ada.png",
+            "locale": "en-GB",
+        }))
+        .into_claims(&identity_config("sub", "email", "nickname"))
+        .unwrap();
+        assert_eq!((claims.sub.as_str(), claims.username.as_deref()), ("auth0|abc123", Some("user")));
+        assert_eq!(claims.profile.name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(claims.profile.picture.as_deref(), Some("https://example.com/ada.png"));
+        assert_eq!(claims.profile.locale.as_deref(), Some("en-GB"));
+        assert_eq!(claims.profile.email_verified, None);
+    }
+
+    #[test]
+    fn auth0_access_tokens_list_several_audiences() {
+        let claims = raw_claims(json!({
+            "sub": "auth0|abc123",
+            "aud": ["api", "https://tenant/userinfo"],
+            "scope": "openid profile",
+        }))
+        .into_claims(&test_config())
+        .unwrap();
+        assert_eq!(claims.sub, "auth0|abc123");
+        assert_eq!(claims.aud, ["api", "https://tenant/userinfo"]);
+
+        let single = raw_claims(json!({ "sub": "user", "aud": "client-id" })).into_claims(&test_config()).unwrap();
+        assert_eq!(single.aud, ["client-id"]);
+        let none = raw_claims(json!({ "sub": "user" })).into_claims(&test_config()).unwrap();
+        assert!(none.aud.is_empty());
+    }
+
+    #[test]
+    fn entra_claims_keyed_by_object_id() {
+        let claims = raw_claims(json!({
+            "sub": "pairwise-subject",
+            "oid": "00000000-0000-0000-0000-000000000001",
+            "preferred_username": "user@contoso.com",
+            "email_verified": "false",
+        }))
+        .into_claims(&identity_config("oid", "email", "preferred_username"))
+        .unwrap();
+        assert_eq!(claims.sub, "00000000-0000-0000-0000-000000000001");
+        assert_eq!(claims.email, None);
+        assert_eq!(claims.username.as_deref(), Some("user@contoso.com"));
+        // Some providers send the flag as a string
+        assert_eq!(claims.profile.email_verified, Some(false));
+    }
+
+    #[test]
+    fn numeric_ids_are_read_as_text() {
+        let claims = raw_claims(json!({ "id": 583231, "login": "octocat" }))
+            .into_claims(&identity_config("id", "email", "login"))
+            .unwrap();
+        assert_eq!((claims.sub.as_str(), claims.username.as_deref()), ("583231", Some("octocat")));
+    }
+
+    #[test]
+    fn tokens_without_a_usable_id_are_refused() {
+        let config = test_config();
+        assert!(raw_claims(json!({ "email": "user@example.com" })).into_claims(&config).is_none());
+        assert!(raw_claims(json!({ "sub": "" })).into_claims(&config).is_none());
+        assert!(raw_claims(json!({ "sub": true })).into_claims(&config).is_none());
+        assert!(raw_claims(json!({ "sub": ["a"] })).into_claims(&config).is_none());
+    }
+
+    #[test]
+    fn single_tenant_claims_have_no_tenant() {
+        let claims = raw_claims(json!({ "sub": "user-1", "iss": "https://idp.example" }))
+            .into_claims(&test_config())
+            .unwrap();
+        assert_eq!(claims.owner(), Owner { user_id: "user-1".into(), tenant_id: None });
+    }
+
+    #[test]
+    fn multi_tenant_claims_keep_tenant_and_subject_apart() {
+        let claims = raw_claims(json!({ "sub": "auth0|user-1", "iss": "https://a.example" }))
+            .into_claims(&multi_tenant_config())
+            .unwrap();
+        assert_eq!(claims.sub, "auth0|user-1");
+        assert_eq!(claims.tenant_id.as_deref(), Some("https://a.example"));
+    }
+
+    #[test]
+    fn multi_tenant_tokens_need_the_tenant_claim() {
+        let mut config = multi_tenant_config();
+        assert!(raw_claims(json!({ "sub": "user-1" })).into_claims(&config).is_none());
+
+        config.tenant_claim = "org_id".into();
+        let claims = raw_claims(json!({ "sub": "user-1", "org_id": "org-7" })).into_claims(&config).unwrap();
+        assert_eq!(claims.tenant_id.as_deref(), Some("org-7"));
+        assert!(raw_claims(json!({ "sub": "user-1", "org_id": "" })).into_claims(&config).is_none());
+    }
+
+    /// A verifier whose JWKS endpoint accepts connections and never answers, so any
+    /// refresh hangs, and whose cache holds `kid-1`, fresh until `fresh_until`
+    async fn verifier_with_hanging_jwks(fresh_until: Instant) -> &'static JwksVerifier {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let jwks_url = format!("http://{}/.well-known/jwks.json", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+
+        let keys = HashMap::from([("kid-1".to_string(), DecodingKey::from_secret(b"key"))]);
+        Box::leak(Box::new(JwksVerifier {
+            cache: Arc::new(RwLock::new(Some(JwksCache { keys, fresh_until }))),
+            refreshing: AtomicBool::new(false),
+            jwks_url,
+            issuer: "https://issuer.example".into(),
+            client_id: "client".into(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn stale_keys_are_served_without_waiting_for_the_refresh() {
+        crate::config::init_test_config();
+        let verifier = verifier_with_hanging_jwks(Instant::now() - Duration::from_secs(1)).await;
+
+        let key = tokio::time::timeout(Duration::from_secs(1), verifier.get_key("kid-1")).await;
+        assert!(matches!(key, Ok(Ok(_))), "stale key should be served at once");
+        assert!(verifier.refreshing.load(Ordering::Acquire), "a background refresh should be running");
+
+        // Later requests keep getting the key and do not start a second refresh
+        assert!(verifier.get_key("kid-1").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn unknown_or_expired_keys_wait_for_the_refresh() {
+        crate::config::init_test_config();
+        let verifier = verifier_with_hanging_jwks(Instant::now() + JWKS_CACHE_TTL).await;
+        let unknown = tokio::time::timeout(Duration::from_millis(200), verifier.get_key("kid-2")).await;
+        assert!(unknown.is_err(), "an unknown kid must wait for the JWKS");
+
+        // Only checkable once the monotonic clock has run for longer than the stale window
+        if let Some(expired) = Instant::now().checked_sub(get_config().jwks_max_stale + Duration::from_secs(1)) {
+            let verifier = verifier_with_hanging_jwks(expired).await;
+            let key = tokio::time::timeout(Duration::from_millis(200), verifier.get_key("kid-1")).await;
+            assert!(key.is_err(), "a key past jwks_max_stale must not be served");
+        }
+    }
+
+    #[test]
+    fn owner_filters_match_tenant_and_user() {
+        let owner = Owner { user_id: "user-1".into(), tenant_id: Some("tenant-a".into()) };
+        assert_eq!(
+            owner.filter(doc! { "id": "order-1" }),
+            doc! { "id": "order-1", "user_id": "user-1", "tenant_id": "tenant-a" }
+        );
+        assert_eq!(
+            owner.metadata_filter(doc! { "filename": "order-1" }),
+            doc! { "filename": "order-1", "metadata.user_id": "user-1", "metadata.tenant_id": "tenant-a" }
+        );
+        assert_eq!(owner.metadata(), doc! { "user_id": "user-1", "tenant_id": "tenant-a" });
+    }
+
+    #[test]
+    fn single_tenant_owners_only_match_documents_without_a_tenant() {
+        let owner = Owner { user_id: "user-1".into(), tenant_id: None };
+        assert_eq!(
+            owner.filter(doc! {}),
+            doc! { "user_id": "user-1", "tenant_id": mongodb::bson::Bson::Null }
+        );
+        assert_eq!(owner.metadata(), doc! { "user_id": "user-1" });
+    }
+}
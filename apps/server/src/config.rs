@@ -0,0 +1,581 @@
+use axum::http::{HeaderName, HeaderValue};
+use jsonwebtoken::Algorithm;
+use mongodb::options::ReadPreference;
+use std::{sync::OnceLock, time::Duration};
+
+use crate::normalize::{StringNormalization, Whitespace};
+use crate::retention::RetentionAction;
+use crate::validation::{OrderNumberFormats, Strictness};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Amazon's product image CDNs, where the extension's `productImage` URLs point
+const DEFAULT_REMOTE_IMAGE_HOSTS: [&str; 2] = ["m.media-amazon.com", "images-na.ssl-images-amazon.com"];
+
+/// Deployment environment from `APP_ENV`; picks defaults that individual variables override
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Local development: Swagger UI on, human-readable multi-line logs, and CORS mirrors
+    /// any origin when `ALLOWED_ORIGINS` is unset
+    Dev,
+    /// Like production, but with Swagger UI on for testing against the deployed API
+    Staging,
+    /// Swagger UI off, single-line logs, `ALLOWED_ORIGINS` required; the default when
+    /// `APP_ENV` is unset
+    Prod,
+}
+
+impl Profile {
+    fn from_env() -> Self {
+        match std::env::var("APP_ENV").as_deref() {
+            Ok("dev") => Profile::Dev,
+            Ok("staging") => Profile::Staging,
+            Err(_) | Ok("prod") => Profile::Prod,
+            Ok(other) => panic!("APP_ENV has an invalid value (dev|staging|prod): {}", other),
+        }
+    }
+}
+
+/// Runtime settings read from the environment once at startup
+#[derive(Debug)]
+pub struct Config {
+    /// Environment profile the remaining defaults were taken from
+    pub profile: Profile,
+    /// Serve Swagger UI and the OpenAPI document
+    pub enable_swagger: bool,
+    /// Multi-line, human-oriented log output instead of one line per event
+    pub pretty_logs: bool,
+    /// Log method, path, status and latency of every request (`order_wizard::access`)
+    pub access_log: bool,
+    /// Exact paths left out of the access log, such as health probes
+    pub access_log_exclude: Vec<String>,
+    /// Route `/orders/` like `/orders` (rewritten, not redirected); off makes paths exact
+    pub tolerate_trailing_slash: bool,
+    /// Stream `GET /orders` from the Mongo cursor instead of buffering the whole list
+    pub stream_order_list: bool,
+    /// How long identical `GET /orders` responses are reused per user; `None` disables the
+    /// cache. Any write by the user invalidates their entries on this instance.
+    pub list_cache_ttl: Option<Duration>,
+    /// Expire orders this many days after they were last written; `None` (the default) keeps
+    /// them forever
+    pub order_retention_days: Option<u32>,
+    /// Whether expired orders are hard-deleted or only marked deleted
+    pub order_retention_action: RetentionAction,
+    /// Add `X-First-Time` to empty order lists, at the cost of an extra count query
+    pub first_time_hint: bool,
+    /// Shown as `productImage` for orders stored without one (see `withPlaceholders`)
+    pub image_placeholder_url: Option<String>,
+    /// Largest product image accepted by `PUT /orders/{id}/image`, in bytes
+    pub max_image_bytes: usize,
+    /// Hosts `GET /orders/{id}/pdf` may download a remote `productImage` from (https only)
+    pub remote_image_hosts: Vec<String>,
+    /// Origins allowed by CORS (exact or `https://*.domain`); `None` mirrors any origin
+    pub allowed_origins: Option<Vec<String>>,
+    /// Serve HTTPS directly when a certificate and key are configured
+    pub tls: Option<TlsConfig>,
+    /// Extra attempts for idempotent Mongo reads that fail transiently
+    pub db_read_retries: u32,
+    /// Delay before the first read retry; doubles on each further attempt
+    pub db_retry_backoff: Duration,
+    /// `Retry-After` sent with the 503 answered when no primary is available (failover)
+    pub db_failover_retry_after: Duration,
+    /// Connection pool bounds; unset keeps the driver default or the URI's option
+    pub mongo_max_pool_size: Option<u32>,
+    pub mongo_min_pool_size: Option<u32>,
+    /// Time allowed to open a connection to a server
+    pub mongo_connect_timeout: Option<Duration>,
+    /// How long an operation waits for a suitable server (e.g. during failover) before failing
+    pub mongo_server_selection_timeout: Option<Duration>,
+    /// Make `source` part of the order upsert key, so one order number can exist per
+    /// marketplace; needs the `(user_id, source, order_number)` unique index
+    pub order_sources: bool,
+    /// Most items one batch request may carry (orders to upsert, ids to get or delete)
+    pub max_batch_size: usize,
+    /// Orders per `bulkWrite` command when a batch upsert is written
+    pub bulk_write_chunk_size: usize,
+    /// Replica-set members read-only endpoints query; writes always go to the primary
+    pub db_read_preference: ReadPreference,
+    /// Server-side `maxTimeMS` for order queries, so Mongo aborts runaway reads itself
+    pub db_max_time: Duration,
+    /// Order-number patterns per source and whether mismatches warn or are rejected
+    pub order_number_formats: OrderNumberFormats,
+    /// Longest order note accepted on create/update, in characters
+    pub max_note_length: usize,
+    /// Most tags one order may carry
+    pub max_tags_per_order: usize,
+    /// Longest tag accepted, in characters
+    pub max_tag_length: usize,
+    /// Page size for list endpoints when `limit` is omitted
+    pub default_page_size: usize,
+    /// Largest `limit` any list endpoint accepts
+    pub max_page_size: usize,
+    /// Signing algorithms accepted in a token's `alg` header; anything else is rejected
+    /// before verification
+    pub jwt_algorithms: Vec<Algorithm>,
+    /// How long past its one-hour TTL a cached JWKS still answers for known keys while it
+    /// is refreshed in the background; unknown keys always wait for a fetch
+    pub jwks_max_stale: Duration,
+    /// Reported by `GET /status` for client banners; `false` announces an ongoing outage
+    pub status_operational: bool,
+    /// Free-text notice shown to users, e.g. an upcoming maintenance
+    pub status_message: Option<String>,
+    /// Announced maintenance window (RFC 3339); the end is optional
+    pub maintenance_start: Option<String>,
+    pub maintenance_end: Option<String>,
+    /// HMAC key for share links; `POST /orders/{id}/share` answers 404 while unset, and
+    /// changing it revokes every link handed out
+    pub share_link_secret: Option<String>,
+    /// How long a share link works after it is created
+    pub share_link_ttl: Duration,
+    /// Prefix for returned share URLs (e.g. the public API origin); unset returns a path
+    pub share_link_base_url: Option<String>,
+    /// Endpoints sent a signed POST for every order created, updated or deleted through
+    /// the API; empty disables webhooks
+    pub webhook_urls: Vec<String>,
+    /// HMAC-SHA256 key for the `X-OW-Signature` header; required when webhooks are on
+    pub webhook_secret: Option<String>,
+    /// Token claim holding the user id that keys all stored data (`IDENTITY_ID_CLAIM`)
+    pub identity_id_claim: String,
+    /// Token claims shown as the user's email and username on `/me` and introspection
+    pub identity_email_claim: String,
+    pub identity_name_claim: String,
+    /// Scope every user's data to the tenant named in their token (`MULTI_TENANT`)
+    pub multi_tenant: bool,
+    /// Token claim naming the tenant when `multi_tenant` is on; defaults to the issuer
+    pub tenant_claim: String,
+    /// Shared secret companion services present to `POST /auth/introspect`; the
+    /// endpoint answers 404 while unset
+    pub introspection_secret: Option<String>,
+    /// Reject JSON request bodies carrying fields the endpoint does not know, naming
+    /// them, instead of ignoring them (`STRICT_JSON_FIELDS`)
+    pub strict_json_fields: bool,
+    /// Whitespace trimming/collapsing applied to order string fields before validation
+    pub string_normalization: StringNormalization,
+    /// Hardening headers added to every response; each can be disabled by setting it empty
+    pub security_headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// PEM certificate chain and private key for direct TLS termination
+#[derive(Debug)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        let profile = Profile::from_env();
+        Self {
+            profile,
+            enable_swagger: env_flag("ENABLE_SWAGGER", profile != Profile::Prod),
+            pretty_logs: env_flag("PRETTY_LOGS", profile == Profile::Dev),
+            access_log: env_flag("ACCESS_LOG", true),
+            access_log_exclude: env_list("ACCESS_LOG_EXCLUDE")
+                .unwrap_or_else(|| vec!["/health".to_string(), "/ready".to_string()]),
+            tolerate_trailing_slash: env_flag("TOLERATE_TRAILING_SLASH", true),
+            stream_order_list: env_flag("STREAM_ORDER_LIST", false),
+            list_cache_ttl: Some(Duration::from_millis(env_parse("LIST_CACHE_TTL_MS", 0)))
+                .filter(|ttl| !ttl.is_zero()),
+            order_retention_days: env_parse_opt("ORDER_RETENTION_DAYS"),
+            order_retention_action: std::env::var("ORDER_RETENTION_ACTION")
+                .map(|v| {
+                    v.parse()
+                        .unwrap_or_else(|_| panic!("ORDER_RETENTION_ACTION has an invalid value (delete|soft-delete): {}", v))
+                })
+                .unwrap_or(RetentionAction::SoftDelete),
+            first_time_hint: env_flag("LIST_FIRST_TIME_HINT", false),
+            image_placeholder_url: std::env::var("IMAGE_PLACEHOLDER_URL").ok().filter(|s| !s.is_empty()),
+            max_image_bytes: env_parse("MAX_IMAGE_BYTES", 1024 * 1024),
+            remote_image_hosts: env_list("REMOTE_IMAGE_HOSTS")
+                .unwrap_or_else(|| DEFAULT_REMOTE_IMAGE_HOSTS.iter().map(|h| h.to_string()).collect())
+                .into_iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+            allowed_origins: allowed_origins_from_env(),
+            tls: tls_from_env(),
+            db_read_retries: env_parse("DB_READ_RETRIES", 2),
+            db_retry_backoff: Duration::from_millis(env_parse("DB_RETRY_BACKOFF_MS", 100)),
+            db_failover_retry_after: Duration::from_secs(env_parse("DB_FAILOVER_RETRY_AFTER_SECS", 5)),
+            mongo_max_pool_size: env_parse_opt("MONGO_MAX_POOL_SIZE"),
+            mongo_min_pool_size: env_parse_opt("MONGO_MIN_POOL_SIZE"),
+            mongo_connect_timeout: env_parse_opt("MONGO_CONNECT_TIMEOUT_MS").map(Duration::from_millis),
+            mongo_server_selection_timeout: env_parse_opt("MONGO_SERVER_SELECTION_TIMEOUT_MS").map(Duration::from_millis),
+            order_sources: env_flag("ORDER_SOURCES", false),
+            max_batch_size: env_parse("MAX_BATCH_SIZE", 100),
+            bulk_write_chunk_size: env_parse("BULK_WRITE_CHUNK_SIZE", 500),
+            db_read_preference: read_preference_from_env(),
+            db_max_time: Duration::from_millis(env_parse("DB_MAX_TIME_MS", 10_000)),
+            order_number_formats: order_number_formats_from_env(),
+            max_note_length: env_parse("MAX_NOTE_LENGTH", 2000),
+            max_tags_per_order: env_parse("MAX_TAGS_PER_ORDER", 20),
+            max_tag_length: env_parse("MAX_TAG_LENGTH", 32),
+            default_page_size: env_parse("DEFAULT_PAGE_SIZE", 50),
+            max_page_size: env_parse("MAX_PAGE_SIZE", 200),
+            jwt_algorithms: jwt_algorithms_from_env(),
+            jwks_max_stale: Duration::from_secs(env_parse("JWKS_MAX_STALE_SECS", 3600)),
+            status_operational: env_flag("STATUS_OPERATIONAL", true),
+            status_message: std::env::var("STATUS_MESSAGE").ok().filter(|s| !s.is_empty()),
+            maintenance_start: std::env::var("MAINTENANCE_START").ok().filter(|s| !s.is_empty()),
+            maintenance_end: std::env::var("MAINTENANCE_END").ok().filter(|s| !s.is_empty()),
+            share_link_secret: std::env::var("SHARE_LINK_SECRET").ok().filter(|s| !s.is_empty()),
+            share_link_ttl: Duration::from_secs(env_parse("SHARE_LINK_TTL_SECS", 7 * 24 * 3600)),
+            share_link_base_url: std::env::var("SHARE_LINK_BASE_URL")
+                .ok()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty()),
+            webhook_urls: env_list("WEBHOOK_URLS").unwrap_or_default(),
+            webhook_secret: std::env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+            identity_id_claim: env_parse("IDENTITY_ID_CLAIM", "sub".to_string()),
+            identity_email_claim: env_parse("IDENTITY_EMAIL_CLAIM", "email".to_string()),
+            identity_name_claim: env_parse("IDENTITY_NAME_CLAIM", "cognito:username".to_string()),
+            multi_tenant: env_flag("MULTI_TENANT", false),
+            tenant_claim: env_parse("TENANT_CLAIM", "iss".to_string()),
+            introspection_secret: std::env::var("INTROSPECTION_SECRET").ok().filter(|s| !s.is_empty()),
+            strict_json_fields: env_flag("STRICT_JSON_FIELDS", false),
+            string_normalization: string_normalization_from_env(),
+            security_headers: security_headers_from_env(),
+        }
+    }
+}
+
+fn tls_from_env() -> Option<TlsConfig> {
+    match (std::env::var("TLS_CERT_PATH"), std::env::var("TLS_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => Some(TlsConfig { cert_path, key_path }),
+        (Err(_), Err(_)) => None,
+        _ => panic!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+    }
+}
+
+/// Only `APP_ENV=dev` may leave `ALLOWED_ORIGINS` unset; elsewhere that would let any site
+/// make credentialed requests
+fn check_allowed_origins(config: &Config) {
+    assert!(
+        config.allowed_origins.is_some() || config.profile == Profile::Dev,
+        "ALLOWED_ORIGINS must be set unless APP_ENV=dev (only dev mirrors any origin)"
+    );
+}
+
+/// Origins as browsers send them in `Origin`: lowercase, no trailing slash, each once
+fn allowed_origins_from_env() -> Option<Vec<String>> {
+    let mut origins: Vec<String> = Vec::new();
+    for origin in env_list("ALLOWED_ORIGINS")? {
+        let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+        if !origins.contains(&origin) {
+            origins.push(origin);
+        }
+    }
+    Some(origins)
+}
+
+fn read_preference_from_env() -> ReadPreference {
+    match std::env::var("DB_READ_PREFERENCE").as_deref() {
+        Err(_) | Ok("primary") => ReadPreference::Primary,
+        Ok("primaryPreferred") => ReadPreference::PrimaryPreferred { options: None },
+        Ok("secondary") => ReadPreference::Secondary { options: None },
+        Ok("secondaryPreferred") => ReadPreference::SecondaryPreferred { options: None },
+        Ok("nearest") => ReadPreference::Nearest { options: None },
+        Ok(other) => panic!("DB_READ_PREFERENCE has an invalid value: {}", other),
+    }
+}
+
+fn jwt_algorithms_from_env() -> Vec<Algorithm> {
+    env_list("JWT_ALGORITHMS")
+        .unwrap_or_else(|| vec!["RS256".to_string()])
+        .iter()
+        .map(|name| {
+            name.parse()
+                .unwrap_or_else(|_| panic!("JWT_ALGORITHMS has an invalid value: {}", name))
+        })
+        .collect()
+}
+
+/// `STRING_NORMALIZATION=productName=trim,note=keep` overrides the per-field defaults
+fn string_normalization_from_env() -> StringNormalization {
+    let mut rules = StringNormalization::default();
+    for entry in env_list("STRING_NORMALIZATION").unwrap_or_default() {
+        let (field, mode) = entry
+            .split_once('=')
+            .unwrap_or_else(|| panic!("STRING_NORMALIZATION entry must be field=mode: {}", entry));
+        let mode: Whitespace = mode
+            .trim()
+            .parse()
+            .unwrap_or_else(|_| panic!("STRING_NORMALIZATION has an invalid mode (keep|trim|collapse): {}", entry));
+        if !rules.set(field.trim(), mode) {
+            panic!("STRING_NORMALIZATION names an unknown field: {}", field);
+        }
+    }
+    rules
+}
+
+/// Amazon order numbers: `123-1234567-1234567`, or `D01-…` for digital orders
+const AMAZON_ORDER_NUMBER: &str = r"^[0-9A-Z]{3}-\d{7}-\d{7}$";
+
+/// `ORDER_NUMBER_PATTERNS=amazon=^\d{3}-\d{7}-\d{7}$;ebay=^\d{2}-\d{5}-\d{5}$` replaces the
+/// default patterns; `default` names the pattern for orders without a source. Entries are
+/// separated by `;`, since patterns may contain commas.
+fn order_number_formats_from_env() -> OrderNumberFormats {
+    order_number_formats(
+        std::env::var("ORDER_NUMBER_CHECK").ok().as_deref(),
+        std::env::var("ORDER_NUMBER_PATTERNS").ok().as_deref(),
+    )
+}
+
+/// Formats from the `ORDER_NUMBER_CHECK` and `ORDER_NUMBER_PATTERNS` values, if set
+fn order_number_formats(check: Option<&str>, patterns: Option<&str>) -> OrderNumberFormats {
+    let strictness = check
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("ORDER_NUMBER_CHECK has an invalid value (off|warn|reject): {}", v))
+        })
+        .unwrap_or(Strictness::Warn);
+    let entries = patterns
+        .map(String::from)
+        .unwrap_or_else(|| format!("default={0};amazon={0}", AMAZON_ORDER_NUMBER));
+
+    let mut formats = OrderNumberFormats { strictness, unsourced: None, by_source: Default::default() };
+    for entry in entries.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (source, pattern) = entry
+            .split_once('=')
+            .unwrap_or_else(|| panic!("ORDER_NUMBER_PATTERNS entry must be source=pattern: {}", entry));
+        let pattern = regex::Regex::new(pattern.trim())
+            .unwrap_or_else(|e| panic!("ORDER_NUMBER_PATTERNS has an invalid pattern for {}: {}", source, e));
+        match source.trim() {
+            "default" => formats.unsourced = Some(pattern),
+            source => {
+                formats.by_source.insert(source.to_string(), pattern);
+            }
+        }
+    }
+    formats
+}
+
+fn security_headers_from_env() -> Vec<(HeaderName, HeaderValue)> {
+    // CSP is off by default: Swagger UI needs a policy tailored to how it is served
+    [
+        ("X_CONTENT_TYPE_OPTIONS", "x-content-type-options", Some("nosniff")),
+        ("X_FRAME_OPTIONS", "x-frame-options", Some("DENY")),
+        ("REFERRER_POLICY", "referrer-policy", Some("no-referrer")),
+        ("CONTENT_SECURITY_POLICY", "content-security-policy", None),
+    ]
+    .into_iter()
+    .filter_map(|(var, header, default)| {
+        let value = std::env::var(var).ok().or(default.map(String::from))?;
+        if value.is_empty() {
+            return None;
+        }
+        let value = HeaderValue::from_str(&value)
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", var, value));
+        Some((HeaderName::from_static(header), value))
+    })
+    .collect()
+}
+
+/// Read a boolean flag, accepting `true`/`1` like `ENABLE_SWAGGER`
+fn env_flag(name: &str, default: bool) -> bool {
+    std::env::var(name)
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(default)
+}
+
+/// Read a comma-separated list, ignoring blank entries; `None` when unset or empty
+fn env_list(name: &str) -> Option<Vec<String>> {
+    let items: Vec<String> = std::env::var(name)
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+    (!items.is_empty()).then_some(items)
+}
+
+/// Read a parsed value, panicking on malformed input so misconfiguration fails at startup
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(v) => v
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value: {}", name, v)),
+        Err(_) => default,
+    }
+}
+
+/// Like [`env_parse`], but `None` when the variable is unset
+fn env_parse_opt<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let v = std::env::var(name).ok()?;
+    Some(v.parse().unwrap_or_else(|_| panic!("{} has an invalid value: {}", name, v)))
+}
+
+pub fn init_config() {
+    let config = Config::from_env();
+    assert!(
+        (1..=config.max_page_size).contains(&config.default_page_size),
+        "DEFAULT_PAGE_SIZE must be between 1 and MAX_PAGE_SIZE"
+    );
+    assert!(config.bulk_write_chunk_size > 0, "BULK_WRITE_CHUNK_SIZE must be positive");
+    assert!(config.max_batch_size > 0, "MAX_BATCH_SIZE must be positive");
+    assert!(config.mongo_max_pool_size != Some(0), "MONGO_MAX_POOL_SIZE must be positive");
+    assert!(config.order_retention_days != Some(0), "ORDER_RETENTION_DAYS must be positive");
+    check_allowed_origins(&config);
+    for (name, time) in [("MAINTENANCE_START", &config.maintenance_start), ("MAINTENANCE_END", &config.maintenance_end)] {
+        if let Some(time) = time {
+            assert!(
+                mongodb::bson::DateTime::parse_rfc3339_str(time).is_ok(),
+                "{} must be an RFC 3339 timestamp: {}",
+                name,
+                time
+            );
+        }
+    }
+    assert!(
+        config.maintenance_end.is_none() || config.maintenance_start.is_some(),
+        "MAINTENANCE_END needs MAINTENANCE_START"
+    );
+    assert!(!config.share_link_ttl.is_zero(), "SHARE_LINK_TTL_SECS must be positive");
+    assert!(
+        config.webhook_urls.is_empty() || config.webhook_secret.is_some(),
+        "WEBHOOK_SECRET must be set when WEBHOOK_URLS is"
+    );
+    for (name, claim) in [
+        ("IDENTITY_ID_CLAIM", &config.identity_id_claim),
+        ("IDENTITY_EMAIL_CLAIM", &config.identity_email_claim),
+        ("IDENTITY_NAME_CLAIM", &config.identity_name_claim),
+        ("TENANT_CLAIM", &config.tenant_claim),
+    ] {
+        assert!(!claim.is_empty(), "{} must not be empty", name);
+    }
+    if let (Some(min), Some(max)) = (config.mongo_min_pool_size, config.mongo_max_pool_size) {
+        assert!(min <= max, "MONGO_MIN_POOL_SIZE must not exceed MONGO_MAX_POOL_SIZE");
+    }
+    for (name, timeout) in [
+        ("MONGO_CONNECT_TIMEOUT_MS", config.mongo_connect_timeout),
+        ("MONGO_SERVER_SELECTION_TIMEOUT_MS", config.mongo_server_selection_timeout),
+    ] {
+        assert!(timeout != Some(Duration::ZERO), "{} must be positive", name);
+    }
+    CONFIG
+        .set(config)
+        .expect("Config already initialized");
+}
+
+pub fn get_config() -> &'static Config {
+    CONFIG.get().expect("Config not initialized")
+}
+
+/// Settings for unit tests: the defaults, plus whatever the test environment sets
+#[cfg(test)]
+pub fn init_test_config() {
+    CONFIG.get_or_init(Config::from_env);
+}
+
+/// The settings `init_test_config` uses, as a value a test can change before passing on
+#[cfg(test)]
+pub fn test_config() -> Config {
+    Config::from_env()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::ValidationErrors;
+
+    /// Field errors `formats` reports for one order number
+    fn errors(formats: &OrderNumberFormats, source: Option<&str>, order_number: &str) -> Vec<String> {
+        let mut errors = ValidationErrors::default();
+        formats.check(&mut errors, "", source, order_number);
+        match errors.into_result() {
+            Ok(()) => Vec::new(),
+            Err(crate::errors::AppError::Validation(fields)) => fields.into_values().flatten().collect(),
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn default_patterns_accept_amazon_order_numbers() {
+        let formats = order_number_formats(Some("reject"), None);
+        for source in [None, Some("amazon")] {
+            assert!(errors(&formats, source, "123-4567890-1234567").is_empty());
+            assert!(errors(&formats, source, "D01-4567890-1234567").is_empty());
+            for bad in ["123-456789-1234567", "1234567890123456", "d01-4567890-1234567", " 123-4567890-1234567"] {
+                assert_eq!(errors(&formats, source, bad).len(), 1, "{:?} should be refused", bad);
+            }
+        }
+        // Sources without a pattern are not checked
+        assert!(errors(&formats, Some("ebay"), "anything").is_empty());
+    }
+
+    #[test]
+    fn configured_patterns_replace_the_defaults_per_source() {
+        let formats = order_number_formats(Some("reject"), Some(r"ebay=^\d{2}-\d{5}-\d{5}$; default = ^X\d+$ ;"));
+        assert!(errors(&formats, Some("ebay"), "12-34567-89012").is_empty());
+        assert_eq!(
+            errors(&formats, Some("ebay"), "123-4567890-1234567"),
+            ["does not match the ebay order number format"]
+        );
+        assert!(errors(&formats, None, "X42").is_empty());
+        assert_eq!(errors(&formats, None, "42"), ["does not match the default order number format"]);
+        // `amazon` is no longer listed, so it is not checked
+        assert!(errors(&formats, Some("amazon"), "42").is_empty());
+    }
+
+    #[test]
+    fn only_reject_mode_refuses_mismatches() {
+        for check in [None, Some("warn"), Some("off")] {
+            assert!(errors(&order_number_formats(check, None), None, "not-an-order").is_empty());
+        }
+        assert!(!errors(&order_number_formats(Some("reject"), None), None, "not-an-order").is_empty());
+        // Empty numbers are left to the required-field check
+        assert!(errors(&order_number_formats(Some("reject"), None), None, " ").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ORDER_NUMBER_CHECK has an invalid value")]
+    fn unknown_check_modes_are_refused() {
+        order_number_formats(Some("strict"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ORDER_NUMBER_PATTERNS has an invalid pattern for amazon")]
+    fn invalid_patterns_are_refused() {
+        order_number_formats(None, Some("amazon=^(\\d+$"));
+    }
+
+    #[test]
+    #[should_panic(expected = "ORDER_NUMBER_PATTERNS entry must be source=pattern")]
+    fn entries_without_a_source_are_refused() {
+        order_number_formats(None, Some(r"^\d+$"));
+    }
+
+    #[test]
+    fn dev_may_mirror_any_origin() {
+        let mut config = test_config();
+        config.profile = Profile::Dev;
+        config.allowed_origins = None;
+        check_allowed_origins(&config);
+    }
+
+    #[test]
+    #[should_panic(expected = "ALLOWED_ORIGINS must be set unless APP_ENV=dev")]
+    fn prod_requires_allowed_origins() {
+        let mut config = test_config();
+        config.profile = Profile::Prod;
+        config.allowed_origins = None;
+        check_allowed_origins(&config);
+    }
+
+    #[test]
+    #[should_panic(expected = "ALLOWED_ORIGINS must be set unless APP_ENV=dev")]
+    fn staging_requires_allowed_origins() {
+        let mut config = test_config();
+        config.profile = Profile::Staging;
+        config.allowed_origins = None;
+        check_allowed_origins(&config);
+    }
+
+    #[test]
+    fn prod_accepts_configured_origins() {
+        let mut config = test_config();
+        config.profile = Profile::Prod;
+        config.allowed_origins = Some(vec!["https://app.example.com".to_string()]);
+        check_allowed_origins(&config);
+    }
+}
@@ -0,0 +1,96 @@
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::get_config;
+
+/// A configured allowed origin: exact, or a `scheme://*.domain` subdomain wildcard
+#[derive(Debug)]
+enum OriginPattern {
+    Exact(String),
+    Wildcard { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let (scheme, rest) = pattern
+            .split_once("://")
+            .ok_or_else(|| format!("origin {:?} is missing a scheme", pattern))?;
+        if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+            return Err(format!("origin {:?} has an invalid scheme", pattern));
+        }
+        if rest.is_empty() || rest.contains('/') {
+            return Err(format!("origin {:?} must be scheme://host[:port] with no path", pattern));
+        }
+
+        match rest.strip_prefix("*.") {
+            Some(domain) if !domain.is_empty() && !domain.contains('*') => Ok(OriginPattern::Wildcard {
+                scheme: format!("{}://", scheme),
+                suffix: format!(".{}", domain),
+            }),
+            Some(_) => Err(format!("origin {:?} has an invalid wildcard", pattern)),
+            None if rest.contains('*') => Err(format!(
+                "origin {:?}: wildcards are only allowed as the leftmost label (https://*.example.com)",
+                pattern
+            )),
+            None => Ok(OriginPattern::Exact(pattern.to_string())),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            OriginPattern::Exact(exact) => origin == exact,
+            OriginPattern::Wildcard { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    !subdomain.is_empty()
+                        && !subdomain.starts_with('.')
+                        && subdomain
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                }),
+        }
+    }
+}
+
+/// Build the CORS layer; mirrors any origin when `ALLOWED_ORIGINS` is unset, which only
+/// `APP_ENV=dev` allows
+pub fn cors_layer() -> CorsLayer {
+    let allow_origin = match &get_config().allowed_origins {
+        Some(origins) => {
+            let patterns: Vec<OriginPattern> = origins
+                .iter()
+                // Browsers only send header-safe origins, so anything else could never match
+                .filter(|o| {
+                    let valid = HeaderValue::from_str(o).is_ok();
+                    if !valid {
+                        tracing::warn!("Ignoring ALLOWED_ORIGINS entry that is not a valid header value: {:?}", o);
+                    }
+                    valid
+                })
+                .map(|o| OriginPattern::parse(o))
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|e| panic!("Invalid ALLOWED_ORIGINS: {}", e));
+
+            AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                origin
+                    .to_str()
+                    .is_ok_and(|origin| patterns.iter().any(|p| p.matches(origin)))
+            })
+        }
+        None => AllowOrigin::mirror_request(),
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::PATCH, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT, header::IF_MATCH])
+        .expose_headers([
+            header::CONTENT_TYPE,
+            header::ETAG,
+            HeaderName::from_static("x-next-cursor"),
+            HeaderName::from_static("x-server-time"),
+            HeaderName::from_static("x-first-time"),
+        ])
+        .allow_credentials(true)
+}
@@ -0,0 +1,85 @@
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september",
+    "october", "november", "december",
+];
+
+/// Current server time as an RFC 3339 string
+pub fn now_rfc3339() -> String {
+    mongodb::bson::DateTime::now()
+        .try_to_rfc3339_string()
+        .expect("current time is representable as RFC 3339")
+}
+
+/// Whole days from a `YYYY-MM-DD` date to today (UTC); `None` if the date is not valid.
+/// Future dates count as 0, since a scraped date can be a day ahead of UTC.
+pub fn days_since(iso_date: &str) -> Option<i64> {
+    let date = parse_iso_date(iso_date)?;
+    let day = days_from_civil(date[0..4].parse().ok()?, date[5..7].parse().ok()?, date[8..10].parse().ok()?);
+    let today = mongodb::bson::DateTime::now().timestamp_millis().div_euclid(86_400_000);
+    Some((today - day).max(0))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Normalize a scraped order date ("December 25, 2024", "25 Dec 2024", "2024-12-25")
+/// to `YYYY-MM-DD`. Returns `None` for anything unrecognized.
+pub fn normalize_order_date(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    if let Some(date) = parse_iso_date(raw) {
+        return Some(date);
+    }
+
+    let cleaned = raw.replace(',', " ");
+    let parts: Vec<&str> = cleaned.split_whitespace().collect();
+    let (month, day, year) = match parts.as_slice() {
+        [m, d, y] if month_number(m).is_some() => (month_number(m)?, *d, *y),
+        [d, m, y] => (month_number(m)?, d.trim_end_matches('.'), *y),
+        _ => return None,
+    };
+
+    format_date(year.parse().ok()?, month, day.parse().ok()?)
+}
+
+/// Validate a strict `YYYY-MM-DD` date, returning it unchanged when valid
+pub fn parse_iso_date(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    if bytes.len() != 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    format_date(s[0..4].parse().ok()?, s[5..7].parse().ok()?, s[8..10].parse().ok()?)
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|m| m.starts_with(&name))
+        .map(|i| i as u32 + 1)
+}
+
+fn format_date(year: i32, month: u32, day: u32) -> Option<String> {
+    let valid = (1..=9999).contains(&year)
+        && (1..=12).contains(&month)
+        && (1..=days_in_month(year, month)).contains(&day);
+    valid.then(|| format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
@@ -1,20 +1,58 @@
-use mongodb::{bson::doc, Client, Collection, Database};
-use std::sync::OnceLock;
+use mongodb::{
+    bson::{doc, Document},
+    error::{Error, ErrorKind, WriteFailure, RETRYABLE_ERROR, SYSTEM_OVERLOADED_ERROR, TRANSIENT_TRANSACTION_ERROR},
+    gridfs::GridFsBucket,
+    options::{ClientOptions, GridFsBucketOptions, ReadPreference, SelectionCriteria},
+    Client, Collection, Database,
+};
+use std::{future::IntoFuture, sync::OnceLock};
 
+use crate::config::get_config;
 use crate::models::OrderEntity;
 
+/// Tracing target for connection and retry logging (`RUST_LOG=order_wizard::db=debug`)
+const TARGET: &str = "order_wizard::db";
+
+/// Server error codes the driver spec treats as retryable for reads
+const RETRYABLE_READ_CODES: [i32; 13] = [
+    11600, 11602, 10107, 13435, 13436, 189, 91, 7, 6, 89, 9001, 134, 262,
+];
+
+/// Server error codes meaning the node lost (or never had) the primary role: a replica-set
+/// election or shutdown is under way. NotWritablePrimary, NotPrimaryNoSecondaryOk,
+/// NotPrimaryOrSecondary, PrimarySteppedDown, InterruptedDueToReplStateChange,
+/// InterruptedAtShutdown, ShutdownInProgress
+const FAILOVER_CODES: [i32; 7] = [10107, 13435, 13436, 189, 11602, 11600, 91];
+
+/// Server error code for an operation the deployment does not allow
+const ILLEGAL_OPERATION: i32 = 20;
+
 static CLIENT: OnceLock<Client> = OnceLock::new();
 static DB: OnceLock<Database> = OnceLock::new();
 
 pub async fn init_db() -> Result<(), mongodb::error::Error> {
     let uri =
         std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
-    let client = Client::with_uri_str(&uri).await?;
+    let mut options = ClientOptions::parse(&uri).await?;
+    let config = get_config();
+    if let Some(size) = config.mongo_max_pool_size {
+        options.max_pool_size = Some(size);
+    }
+    if let Some(size) = config.mongo_min_pool_size {
+        options.min_pool_size = Some(size);
+    }
+    if let Some(timeout) = config.mongo_connect_timeout {
+        options.connect_timeout = Some(timeout);
+    }
+    if let Some(timeout) = config.mongo_server_selection_timeout {
+        options.server_selection_timeout = Some(timeout);
+    }
+    let client = Client::with_options(options)?;
     let db = client.database("order_wizard");
 
     // Ping to verify connection
     db.run_command(doc! { "ping": 1 }).await?;
-    tracing::info!("Connected to MongoDB");
+    tracing::info!(target: TARGET, "Connected to MongoDB");
 
     CLIENT.set(client).expect("Client already initialized");
     DB.set(db).expect("Database already initialized");
@@ -22,6 +60,11 @@ pub async fn init_db() -> Result<(), mongodb::error::Error> {
     Ok(())
 }
 
+/// Round-trip to the server; used by the readiness probe
+pub async fn ping() -> Result<(), Error> {
+    get_db().run_command(doc! { "ping": 1 }).await.map(|_| ())
+}
+
 pub fn get_client() -> &'static Client {
     CLIENT.get().expect("Client not initialized")
 }
@@ -33,3 +76,159 @@ pub fn get_db() -> &'static Database {
 pub fn orders_collection() -> Collection<OrderEntity> {
     get_db().collection("orders")
 }
+
+/// Selection for read-only endpoints (`DB_READ_PREFERENCE`). Secondaries may lag the
+/// primary, so anything that reads before writing, or relies on seeing its own writes,
+/// uses [`primary`] instead.
+pub fn read_only() -> SelectionCriteria {
+    SelectionCriteria::ReadPreference(get_config().db_read_preference.clone())
+}
+
+pub fn primary() -> SelectionCriteria {
+    SelectionCriteria::ReadPreference(ReadPreference::Primary)
+}
+
+/// Ids of hard-deleted orders (`id`, `user_id`, `purged_at`), so lookups can answer 410
+/// instead of 404; entries expire via a TTL index
+pub fn tombstones_collection() -> Collection<Document> {
+    get_db().collection("order_tombstones")
+}
+
+/// Name of the GridFS bucket for product images (`<name>.files` / `<name>.chunks`)
+pub const IMAGES_BUCKET: &str = "order_images";
+
+/// GridFS bucket holding uploaded product images, one file per order
+pub fn images_bucket() -> GridFsBucket {
+    get_db().gridfs_bucket(
+        GridFsBucketOptions::builder()
+            .bucket_name(IMAGES_BUCKET.to_string())
+            .build(),
+    )
+}
+
+/// Run an idempotent read, retrying transient failures with exponential backoff.
+///
+/// Only for reads (`find`, `find_one`, `count_documents`, ...): writes must not be
+/// replayed unless the driver's own retryable-write semantics apply.
+pub async fn retry_read<T, F, Fut>(operation: &str, mut read: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: IntoFuture<Output = Result<T, Error>>,
+{
+    let config = get_config();
+    let mut attempt = 0;
+
+    loop {
+        match read().await {
+            Err(e) if attempt < config.db_read_retries && is_transient(&e) => {
+                attempt += 1;
+                let delay = config.db_retry_backoff * 2u32.pow(attempt - 1);
+                tracing::warn!(
+                    target: TARGET,
+                    "{} failed (retry {}/{} in {:?}): {}",
+                    operation,
+                    attempt,
+                    config.db_read_retries,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Server selection failures are not retried here: the driver has already waited out
+/// `serverSelectionTimeoutMS`, and [`is_failover`] answers them with 503 `Retry-After`.
+fn is_transient(err: &Error) -> bool {
+    if err.contains_label(RETRYABLE_ERROR) || err.contains_label(SYSTEM_OVERLOADED_ERROR) {
+        return true;
+    }
+    match err.kind.as_ref() {
+        ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } => true,
+        ErrorKind::Command(command) => RETRYABLE_READ_CODES.contains(&command.code),
+        _ => false,
+    }
+}
+
+/// Whether an error means no primary could take the operation, typically mid-election.
+/// By the time the driver hands one back its own retry has run, so the caller can only
+/// ask the client to come back shortly.
+pub fn is_failover(err: &Error) -> bool {
+    if err.contains_label(RETRYABLE_ERROR) || err.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+        return true;
+    }
+    match err.kind.as_ref() {
+        ErrorKind::ServerSelection { .. } => true,
+        ErrorKind::Command(command) => FAILOVER_CODES.contains(&command.code),
+        ErrorKind::Write(WriteFailure::WriteConcernError(concern)) => FAILOVER_CODES.contains(&concern.code),
+        _ => false,
+    }
+}
+
+/// Whether an error means the deployment cannot run transactions: a standalone server,
+/// like the one `just db` starts, rather than a replica set or mongos. The driver says so
+/// itself once it knows the topology; before that, the server refuses the first operation.
+pub fn is_transactions_unsupported(err: &Error) -> bool {
+    match err.kind.as_ref() {
+        ErrorKind::Transaction { message, .. } => message.contains("not supported by this deployment"),
+        ErrorKind::Command(command) => {
+            command.code == ILLEGAL_OPERATION && command.message.contains("Transaction numbers")
+        }
+        _ => false,
+    }
+}
+
+/// Connection for integration tests, which need a running MongoDB: the one from
+/// `just db` unless `MONGODB_URI` says otherwise. They are `#[ignore]`d so the unit
+/// tests run anywhere; `cargo test -- --include-ignored` runs them too.
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+    use std::future::Future;
+    use tokio::runtime::Runtime;
+
+    /// Database the tests write to; each test uses its own user ids, so runs can share it
+    const TEST_DB: &str = "order_wizard_test";
+
+    /// The driver's background tasks live on the runtime that created the client, so
+    /// every integration test runs on this one instead of its own `#[tokio::test]` runtime
+    fn runtime() -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(|| Runtime::new().expect("Failed to start test runtime"))
+    }
+
+    /// Run an integration test body against the test database
+    pub fn run<F: Future<Output = ()>>(test: F) {
+        runtime().block_on(async {
+            connect().await;
+            test.await
+        })
+    }
+
+    async fn connect() {
+        crate::config::init_test_config();
+        if DB.get().is_some() {
+            return;
+        }
+        let uri = std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
+        let client = Client::with_uri_str(&uri).await.expect("Invalid MONGODB_URI");
+        let db = client.database(TEST_DB);
+        db.run_command(doc! { "ping": 1 }).await.expect("Integration tests need MongoDB (just db)");
+        let _ = CLIENT.set(client);
+        let _ = DB.set(db);
+    }
+
+    /// A user id no other test run has written under
+    pub fn unique_user() -> String {
+        format!("test-{}", uuid::Uuid::new_v4())
+    }
+
+    /// Whether the server can run transactions, i.e. is a replica set or mongos; the
+    /// single node from `just db` is neither
+    pub async fn supports_transactions() -> bool {
+        let hello = get_db().run_command(doc! { "hello": 1 }).await.expect("hello failed");
+        hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid")
+    }
+}
@@ -1,16 +1,31 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use mongodb::error::ErrorKind;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use utoipa::ToSchema;
 
+use crate::config::get_config;
+use crate::db::{is_failover, is_transactions_unsupported};
+
+/// Server error code for an operation that ran past its `maxTimeMS`
+const MAX_TIME_MS_EXPIRED: i32 = 50;
+
+/// Validation messages keyed by request field (e.g. `orderNumber`, `orders[2].id`)
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
+
 /// API error response
 #[derive(Debug, Serialize, ToSchema)]
 pub struct ApiError {
     pub code: &'static str,
     pub message: String,
+    /// Per-field messages, present for `VALIDATION_ERROR` and `BATCH_REJECTED`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!({ "orderNumber": ["must not be empty"] }))]
+    pub fields: Option<FieldErrors>,
 }
 
 /// Application errors - fail fast with clear messages
@@ -18,10 +33,32 @@ pub struct ApiError {
 pub enum AppError {
     /// Resource not found
     NotFound(&'static str),
+    /// Resource existed but was permanently deleted
+    Gone(&'static str),
     /// Invalid request data
     BadRequest(String),
+    /// One or more request fields failed validation
+    Validation(FieldErrors),
+    /// `If-Match` did not match the current representation
+    PreconditionFailed(String),
+    /// The request clashes with another stored resource
+    Conflict(String),
+    /// An atomic batch was rolled back because some items failed to write
+    BatchRejected(FieldErrors),
+    /// Request body exceeds the allowed size
+    PayloadTooLarge(String),
+    /// Request body has a content type the endpoint does not accept
+    UnsupportedMediaType(String),
+    /// Database aborted the operation after its `maxTimeMS`
+    DatabaseTimeout,
+    /// No primary was available, e.g. during a replica-set election; worth retrying shortly
+    DatabaseUnavailable,
+    /// The operation needs a transaction, which a standalone MongoDB cannot run
+    TransactionsUnsupported,
     /// Database operation failed
     Database(String),
+    /// Server-side failure unrelated to the database
+    Internal(String),
 }
 
 impl AppError {
@@ -29,24 +66,109 @@ impl AppError {
         AppError::NotFound(resource)
     }
 
+    pub fn gone(resource: &'static str) -> Self {
+        AppError::Gone(resource)
+    }
+
     pub fn bad_request(message: impl Into<String>) -> Self {
         AppError::BadRequest(message.into())
     }
 
+    pub fn precondition_failed(message: impl Into<String>) -> Self {
+        AppError::PreconditionFailed(message.into())
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        AppError::Conflict(message.into())
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        AppError::PayloadTooLarge(message.into())
+    }
+
+    pub fn unsupported_media_type(message: impl Into<String>) -> Self {
+        AppError::UnsupportedMediaType(message.into())
+    }
+
     pub fn database(err: mongodb::error::Error) -> Self {
-        AppError::Database(err.to_string())
+        match *err.kind {
+            ErrorKind::Command(ref command) if command.code == MAX_TIME_MS_EXPIRED => {
+                tracing::warn!("Database operation exceeded maxTimeMS: {}", err);
+                AppError::DatabaseTimeout
+            }
+            _ if is_transactions_unsupported(&err) => {
+                tracing::warn!("Database does not support transactions: {}", err);
+                AppError::TransactionsUnsupported
+            }
+            _ if is_failover(&err) => {
+                tracing::warn!("Database primary unavailable: {}", err);
+                AppError::DatabaseUnavailable
+            }
+            _ => AppError::Database(err.to_string()),
+        }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let mut fields = None;
         let (status, code, message) = match self {
             AppError::NotFound(resource) => (
                 StatusCode::NOT_FOUND,
                 "NOT_FOUND",
                 format!("{} not found", resource),
             ),
+            AppError::Gone(resource) => (
+                StatusCode::GONE,
+                "GONE",
+                format!("{} was permanently deleted", resource),
+            ),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "BAD_REQUEST", msg),
+            AppError::Validation(errors) => {
+                fields = Some(errors);
+                (
+                    StatusCode::BAD_REQUEST,
+                    "VALIDATION_ERROR",
+                    "Request validation failed".to_string(),
+                )
+            }
+            AppError::PreconditionFailed(msg) => {
+                (StatusCode::PRECONDITION_FAILED, "PRECONDITION_FAILED", msg)
+            }
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, "CONFLICT", msg),
+            AppError::BatchRejected(errors) => {
+                fields = Some(errors);
+                (
+                    StatusCode::CONFLICT,
+                    "BATCH_REJECTED",
+                    "Batch rolled back; no orders were written".to_string(),
+                )
+            }
+            AppError::PayloadTooLarge(msg) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "PAYLOAD_TOO_LARGE", msg)
+            }
+            AppError::UnsupportedMediaType(msg) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "UNSUPPORTED_MEDIA_TYPE", msg)
+            }
+            AppError::DatabaseTimeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "DATABASE_TIMEOUT",
+                "Database operation timed out".to_string(),
+            ),
+            AppError::DatabaseUnavailable => {
+                let retry_after = get_config().db_failover_retry_after.as_secs().to_string();
+                let body = Json(ApiError {
+                    code: "DATABASE_UNAVAILABLE",
+                    message: "Database is temporarily unavailable, retry shortly".to_string(),
+                    fields: None,
+                });
+                return (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry_after)], body).into_response();
+            }
+            AppError::TransactionsUnsupported => (
+                StatusCode::NOT_IMPLEMENTED,
+                "TRANSACTIONS_UNSUPPORTED",
+                "This operation needs MongoDB transactions; the database must be a replica set or sharded cluster".to_string(),
+            ),
             AppError::Database(msg) => {
                 tracing::error!("Database error: {}", msg);
                 (
@@ -55,9 +177,17 @@ impl IntoResponse for AppError {
                     "Database operation failed".to_string(),
                 )
             }
+            AppError::Internal(msg) => {
+                tracing::error!("Internal error: {}", msg);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "INTERNAL_ERROR",
+                    "Internal server error".to_string(),
+                )
+            }
         };
 
-        (status, Json(ApiError { code, message })).into_response()
+        (status, Json(ApiError { code, message, fields })).into_response()
     }
 }
 
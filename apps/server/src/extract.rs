@@ -0,0 +1,284 @@
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, FromRequestParts, Query, Request},
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::de::DeserializeOwned;
+
+use crate::config::get_config;
+use crate::errors::AppError;
+use crate::models::{NestedOrder, Order, OrderBody, OrderShape, PageParams, PlaceholderParams, ShapeParams};
+use crate::validation::ValidationErrors;
+
+/// `Json` body extractor whose rejections use the `ApiError` shape: 415 when the request
+/// is not `Content-Type: application/json`, 413 when too large, 400 when it does not parse
+pub struct AppJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for AppJson<T>
+where
+    T: DeserializeOwned,
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if get_config().strict_json_fields {
+            let Json(body) = Json::<serde_json::Value>::from_request(request, state).await?;
+            return strict_from_value(body).map(AppJson);
+        }
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(AppJson(value)),
+            Err(rejection) => Err(rejection.into()),
+        }
+    }
+}
+
+/// Deserialize under `STRICT_JSON_FIELDS`: any field the target type does not have is a
+/// validation error naming it, instead of being dropped (a typo like `prodcutName`)
+fn strict_from_value<T: DeserializeOwned>(body: serde_json::Value) -> Result<T, AppError> {
+    let mut errors = ValidationErrors::default();
+    let value = serde_ignored::deserialize(body, |path| errors.add(field_path(&path), "is not a known field"))
+        .map_err(|e| AppError::bad_request(format!("Failed to deserialize the JSON body into the target type: {}", e)))?;
+    errors.into_result()?;
+    Ok(value)
+}
+
+/// `orders[2].prodcutName`, the way validation errors name nested fields
+fn field_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => format!("{}[{}]", field_path(parent), index),
+        serde_ignored::Path::Map { parent, key } => match field_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => field_path(parent),
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection.status() {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                AppError::unsupported_media_type("Expected Content-Type: application/json")
+            }
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::payload_too_large(rejection.body_text()),
+            _ => AppError::bad_request(rejection.body_text()),
+        }
+    }
+}
+
+/// Whether an empty `productImage` is filled with `IMAGE_PLACEHOLDER_URL` in responses;
+/// off when no placeholder is configured or the client sent `withPlaceholders=false`.
+/// Stored orders are never changed.
+#[derive(Debug, Clone, Copy)]
+pub struct Placeholders(bool);
+
+impl Placeholders {
+    pub fn apply(self, mut order: Order) -> Order {
+        if let (true, Some(placeholder)) = (self.0, &get_config().image_placeholder_url) {
+            if order.product_image.trim().is_empty() {
+                order.product_image = placeholder.clone();
+            }
+        }
+        order
+    }
+}
+
+impl<S> FromRequestParts<S> for Placeholders
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PlaceholderParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::bad_request(e.body_text()))?;
+        Ok(Placeholders(params.with_placeholders != Some(false)))
+    }
+}
+
+/// Response shape requested with `shape`; flat unless the client asked for `nested`
+#[derive(Debug, Clone, Copy)]
+pub struct Shape(OrderShape);
+
+impl Shape {
+    pub fn apply(self, order: Order) -> OrderBody {
+        match self.0 {
+            OrderShape::Flat => OrderBody::Flat(Box::new(order)),
+            OrderShape::Nested => OrderBody::Nested(NestedOrder::from(order)),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for Shape
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<ShapeParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::bad_request(e.body_text()))?;
+        Ok(Shape(params.shape.unwrap_or_default()))
+    }
+}
+
+/// One page request: `limit` is already bounded by the configured maximum
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub limit: usize,
+    /// The decoded cursor: the last `id` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// `X-Next-Cursor` value for a page ending at `id`. Order ids are client-chosen text that
+/// may not be a valid header value, so the id is sent base64url-encoded.
+pub fn encode_cursor(id: &str) -> String {
+    URL_SAFE_NO_PAD.encode(id)
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    String::from_utf8(bytes).ok().filter(|id| !id.is_empty())
+}
+
+/// `limit`/`cursor` query parameters checked against the configured page-size policy.
+/// `None` when the client sent neither, so endpoints that historically returned
+/// everything can keep doing so; a `cursor` alone gets the configured default size.
+#[derive(Debug, Clone)]
+pub struct Pagination(pub Option<Page>);
+
+impl<S> FromRequestParts<S> for Pagination
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PageParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| AppError::bad_request(e.body_text()))?;
+        if params.limit.is_none() && params.cursor.is_none() {
+            return Ok(Pagination(None));
+        }
+
+        let mut errors = ValidationErrors::default();
+        let limit = page_limit(params.limit.as_deref(), &mut errors);
+        let cursor = params.cursor.as_deref().map(|c| (c, decode_cursor(c)));
+        match cursor {
+            Some((c, _)) if c.trim().is_empty() => errors.add("cursor", "must not be empty"),
+            Some((_, None)) => errors.add("cursor", "must be an X-Next-Cursor value"),
+            _ => {}
+        }
+        errors.into_result()?;
+
+        Ok(Pagination(Some(Page { limit, cursor: cursor.and_then(|(_, id)| id) })))
+    }
+}
+
+/// A `limit` query parameter checked against the configured page sizes: the default when
+/// absent, an error naming `limit` when not a number between 1 and the maximum
+pub fn page_limit(limit: Option<&str>, errors: &mut ValidationErrors) -> usize {
+    let config = get_config();
+    match limit.map(str::parse::<usize>) {
+        None => config.default_page_size,
+        Some(Ok(limit)) if (1..=config.max_page_size).contains(&limit) => limit,
+        Some(_) => {
+            errors.add("limit", format!("must be between 1 and {}", config.max_page_size));
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{BatchUpsertRequest, CreateOrderRequest};
+    use serde_ignored::Path;
+    use serde_json::json;
+
+    fn unknown_fields<T: DeserializeOwned>(body: serde_json::Value) -> Vec<(String, Vec<String>)> {
+        match strict_from_value::<T>(body) {
+            Err(AppError::Validation(errors)) => errors.into_iter().collect(),
+            Err(e) => panic!("expected a validation error, got {:?}", e),
+            Ok(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn misspelled_fields_are_named() {
+        let errors = unknown_fields::<CreateOrderRequest>(CreateOrderRequest::fixture_json(json!({ "prodcutName": "Headphones" })));
+        assert_eq!(errors, [("prodcutName".to_string(), vec!["is not a known field".to_string()])]);
+    }
+
+    #[test]
+    fn nested_fields_are_named_with_their_path() {
+        let order = |overrides| CreateOrderRequest::fixture_json(overrides);
+        let orders = json!({ "orders": [order(json!({})), order(json!({})), order(json!({ "prodcutName": "x" }))] });
+        let errors = unknown_fields::<BatchUpsertRequest>(orders);
+        assert_eq!(errors.iter().map(|(field, _)| field.as_str()).collect::<Vec<_>>(), ["orders[2].prodcutName"]);
+    }
+
+    #[test]
+    fn known_fields_pass() {
+        // `userId` is accepted (and ignored), not reported as unknown
+        let body = CreateOrderRequest::fixture_json(json!({ "note": "gift", "userId": "someone-else" }));
+        assert_eq!(unknown_fields::<CreateOrderRequest>(body), []);
+    }
+
+    #[test]
+    fn limits_share_one_policy_and_error_shape() {
+        crate::config::init_test_config();
+        let config = get_config();
+        let limit = |value: Option<&str>| {
+            let mut errors = ValidationErrors::default();
+            let limit = page_limit(value, &mut errors);
+            match errors.into_result() {
+                Ok(()) => Ok(limit),
+                Err(AppError::Validation(fields)) => Err(fields.into_keys().collect::<Vec<_>>()),
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        };
+        let max = config.max_page_size.to_string();
+        let over = (config.max_page_size + 1).to_string();
+        assert_eq!(limit(None), Ok(config.default_page_size));
+        assert_eq!(limit(Some("1")), Ok(1));
+        assert_eq!(limit(Some(&max)), Ok(config.max_page_size));
+        for invalid in ["0", over.as_str(), "ten", "-1", ""] {
+            assert_eq!(limit(Some(invalid)), Err(vec!["limit".to_string()]), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn cursors_carry_any_order_id_in_a_header() {
+        for id in ["order-1", "Bestellung-Größe", "注文 7", "a|b/c"] {
+            let cursor = encode_cursor(id);
+            assert!(axum::http::HeaderValue::from_str(&cursor).is_ok(), "{} is not a header value", cursor);
+            assert_eq!(decode_cursor(&cursor).as_deref(), Some(id));
+        }
+        assert_eq!(decode_cursor("not base64!"), None);
+        assert_eq!(decode_cursor(&URL_SAFE_NO_PAD.encode([0xff, 0xfe])), None);
+    }
+
+    #[test]
+    fn field_paths_read_like_validation_fields() {
+        let root = Path::Root;
+        let orders = Path::Map { parent: &root, key: "orders".to_string() };
+        let item = Path::Seq { parent: &orders, index: 2 };
+        let field = Path::Map { parent: &item, key: "prodcutName".to_string() };
+        assert_eq!(field_path(&root), "");
+        assert_eq!(field_path(&orders), "orders");
+        assert_eq!(field_path(&item), "orders[2]");
+        assert_eq!(field_path(&field), "orders[2].prodcutName");
+
+        let optional = Path::Some { parent: &field };
+        assert_eq!(field_path(&optional), "orders[2].prodcutName");
+    }
+}
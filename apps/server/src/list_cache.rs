@@ -0,0 +1,149 @@
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::Request,
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use moka::future::Cache;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use crate::auth::{Claims, Owner};
+use crate::config::get_config;
+use crate::errors::AppResult;
+
+/// Most cached list responses kept across all users
+const MAX_ENTRIES: u64 = 10_000;
+
+/// Owner, the owner's write generation when the list was read, and the raw query string
+type CacheKey = (Owner, u64, String);
+
+/// Response parts that can be replayed to every caller sharing a key
+#[derive(Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    async fn capture(response: Response) -> Self {
+        let (parts, body) = response.into_parts();
+        // List responses are buffered already; a failure here only loses the cached copy
+        let body = to_bytes(body, usize::MAX).await.unwrap_or_default();
+        Self { status: parts.status, headers: parts.headers, body }
+    }
+
+    fn replay(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+static CACHE: OnceLock<Cache<CacheKey, CachedResponse>> = OnceLock::new();
+
+/// Per-owner write generation, replaced around every write so lists read before a write
+/// are never served after it. Generations come from one global counter and are never
+/// reused; an owner without one (never written, or idle longer than the entry lives) is at
+/// 0, and every list cached under an older 0 has expired by then since entries here
+/// outlive cached lists.
+static GENERATIONS: OnceLock<Cache<Owner, u64>> = OnceLock::new();
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+fn generations(ttl: Duration) -> &'static Cache<Owner, u64> {
+    GENERATIONS.get_or_init(|| Cache::builder().time_to_live(ttl * 2).build())
+}
+
+async fn generation(owner: &Owner, ttl: Duration) -> u64 {
+    generations(ttl).get(owner).await.unwrap_or(0)
+}
+
+async fn bump(owner: &Owner, ttl: Duration) {
+    let next = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    generations(ttl).insert(owner.clone(), next).await;
+}
+
+/// Serve `GET /orders` from the short-lived per-user cache (`LIST_CACHE_TTL_MS`), running
+/// `load` on a miss. Concurrent identical requests share one load; errors are not cached.
+pub async fn cached_list<F>(owner: &Owner, query: &str, load: F) -> AppResult<Response>
+where
+    F: Future<Output = AppResult<Response>>,
+{
+    let Some(ttl) = get_config().list_cache_ttl else {
+        return load.await;
+    };
+    let cache = CACHE.get_or_init(|| Cache::builder().max_capacity(MAX_ENTRIES).time_to_live(ttl).build());
+
+    let key = (owner.clone(), generation(owner, ttl).await, query.to_string());
+    let result = cache
+        .try_get_with(key, async {
+            match load.await {
+                Ok(response) => Ok(CachedResponse::capture(response).await),
+                Err(e) => Err(CachedResponse::capture(e.into_response()).await),
+            }
+        })
+        .await;
+    Ok(match result {
+        Ok(cached) => cached.replay(),
+        Err(error) => CachedResponse::clone(&error).replay(),
+    })
+}
+
+/// Bust the caller's cached lists around any mutating request. The generation is bumped
+/// before the write, so reads racing it are cached under a key nobody asks for again,
+/// and after it, so reads made while it ran are dropped too.
+pub async fn invalidate_on_write(request: Request, next: Next) -> Response {
+    let owner = request
+        .extensions()
+        .get::<Claims>()
+        .map(Claims::owner)
+        .filter(|_| !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS));
+    let (Some(owner), Some(ttl)) = (owner, get_config().list_cache_ttl) else {
+        return next.run(request).await;
+    };
+
+    bump(&owner, ttl).await;
+    let response = next.run(request).await;
+    bump(&owner, ttl).await;
+    response
+}
+
+/// Bust an owner's cached lists after a write made outside a request, such as retention
+pub async fn invalidate(owner: &Owner) {
+    if let Some(ttl) = get_config().list_cache_ttl {
+        bump(owner, ttl).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(user_id: &str) -> Owner {
+        Owner { user_id: user_id.to_string(), tenant_id: None }
+    }
+
+    #[tokio::test]
+    async fn writes_move_the_owner_to_a_generation_never_used_before() {
+        let ttl = Duration::from_secs(60);
+        let (writer, reader) = (owner("list-cache-writer"), owner("list-cache-reader"));
+        assert_eq!(generation(&writer, ttl).await, 0);
+
+        bump(&writer, ttl).await;
+        let first = generation(&writer, ttl).await;
+        bump(&writer, ttl).await;
+        let second = generation(&writer, ttl).await;
+        assert!(first > 0 && second > first, "{} then {}", first, second);
+        assert_eq!(generation(&reader, ttl).await, 0);
+    }
+}
@@ -1,14 +1,31 @@
+mod access_log;
+mod api_docs;
 mod auth;
+mod config;
+mod cors;
+mod dates;
 mod db;
 mod errors;
+mod extract;
+mod list_cache;
 mod models;
+mod normalize;
+mod pdf;
+mod recompute;
+mod retention;
 mod routes;
+#[cfg(debug_assertions)]
+mod schema_check;
+mod security_headers;
+mod status_format;
+mod validation;
+mod webhooks;
 
-use auth::{auth_middleware, AuthError, AuthUser, JwksVerifier};
-use axum::{middleware, Json};
+use auth::{auth_middleware, AuthError, AuthUser, JwksVerifier, UserProfile};
+use axum::{http::{header, StatusCode}, middleware, response::IntoResponse, Json};
+use axum_server::tls_rustls::RustlsConfig;
 use serde::Serialize;
-use axum::http::{header, Method};
-use tower_http::cors::{AllowOrigin, CorsLayer};
+use std::net::SocketAddr;
 use tower_governor::{governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor, GovernorLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use utoipa::{
@@ -18,11 +35,69 @@ use utoipa::{
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_swagger_ui::SwaggerUi;
 
+/// Tracing target for startup (`RUST_LOG=order_wizard::boot=debug` or `--verbose-boot`)
+const BOOT_TARGET: &str = "order_wizard::boot";
+
 #[derive(Serialize, ToSchema)]
 struct Health {
     status: String,
 }
 
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Down,
+}
+
+impl From<bool> for CheckStatus {
+    fn from(ok: bool) -> Self {
+        if ok { CheckStatus::Ok } else { CheckStatus::Down }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct Readiness {
+    /// MongoDB answers a ping
+    mongo: CheckStatus,
+    /// Cognito signing keys are cached or fetchable, so tokens can be verified
+    jwks: CheckStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct VersionInfo {
+    /// Crate version from Cargo.toml
+    #[schema(example = "1.0.10")]
+    version: &'static str,
+    /// Git commit the binary was built from
+    #[schema(example = "b7c3040")]
+    git_sha: &'static str,
+    /// Build time (RFC 3339)
+    build_timestamp: String,
+}
+
+/// Human-facing service notice for client banners; machine probes use `/ready`
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct ServiceStatus {
+    /// `false` during an announced outage
+    operational: bool,
+    /// Notice to show users, if any
+    #[schema(example = "Scheduled maintenance tonight at 2am UTC")]
+    message: Option<String>,
+    scheduled_maintenance: Option<MaintenanceWindow>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MaintenanceWindow {
+    /// Start of the window (RFC 3339)
+    #[schema(example = "2026-01-15T02:00:00Z")]
+    start: String,
+    /// End of the window (RFC 3339), if announced
+    end: Option<String>,
+}
+
 #[derive(Serialize, ToSchema)]
 struct UserInfo {
     /// User subject (unique identifier)
@@ -31,6 +106,12 @@ struct UserInfo {
     email: Option<String>,
     /// Cognito username
     username: Option<String>,
+    /// Tenant the user's data belongs to; only present when `MULTI_TENANT` is on
+    #[serde(rename = "tenantId", skip_serializing_if = "Option::is_none")]
+    tenant_id: Option<String>,
+    /// Profile claims present in the token
+    #[serde(flatten)]
+    profile: UserProfile,
 }
 
 
@@ -50,6 +131,80 @@ async fn health() -> Json<Health> {
     })
 }
 
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "Health",
+    summary = "Readiness check",
+    description = "Checks the dependencies needed to serve requests: MongoDB and the Cognito JWKS",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = Readiness),
+        (status = 503, description = "A dependency is unavailable", body = Readiness)
+    )
+)]
+async fn ready() -> (StatusCode, Json<Readiness>) {
+    let (mongo, jwks) = tokio::join!(db::ping(), JwksVerifier::keys_available());
+    if let Err(e) = &mongo {
+        tracing::warn!("Readiness: MongoDB ping failed: {}", e);
+    }
+
+    let status = if mongo.is_ok() && jwks {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Readiness { mongo: mongo.is_ok().into(), jwks: jwks.into() }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "Health",
+    summary = "Build information",
+    description = "Returns the version, git commit and build time of the running server",
+    responses(
+        (status = 200, description = "Build information", body = VersionInfo)
+    )
+)]
+async fn version() -> Json<VersionInfo> {
+    let build_secs: i64 = env!("BUILD_TIMESTAMP")
+        .parse()
+        .expect("BUILD_TIMESTAMP is set by build.rs");
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        build_timestamp: mongodb::bson::DateTime::from_millis(build_secs * 1000)
+            .try_to_rfc3339_string()
+            .expect("build time is representable as RFC 3339"),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "Health",
+    summary = "Service status notice",
+    description = "Returns the operator-configured status and maintenance notice (`STATUS_*`, \
+        `MAINTENANCE_*`) for clients to show as a banner. Cacheable for a minute.",
+    responses(
+        (status = 200, description = "Current service notice", body = ServiceStatus)
+    )
+)]
+async fn status() -> impl IntoResponse {
+    let config = config::get_config();
+    let scheduled_maintenance = config.maintenance_start.clone().map(|start| MaintenanceWindow {
+        start,
+        end: config.maintenance_end.clone(),
+    });
+    (
+        [(header::CACHE_CONTROL, "public, max-age=60")],
+        Json(ServiceStatus {
+            operational: config.status_operational,
+            message: config.status_message.clone(),
+            scheduled_maintenance,
+        }),
+    )
+}
 
 #[utoipa::path(
     get,
@@ -68,6 +223,8 @@ async fn me(AuthUser(claims): AuthUser) -> Json<UserInfo> {
         sub: claims.sub,
         email: claims.email,
         username: claims.username,
+        tenant_id: claims.tenant_id,
+        profile: claims.profile,
     })
 }
 
@@ -86,6 +243,15 @@ impl Modify for SecurityAddon {
                         .build(),
                 ),
             );
+            components.add_security_scheme(
+                "introspection_secret",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some("Shared secret for server-to-server introspection (INTROSPECTION_SECRET)"))
+                        .build(),
+                ),
+            );
         }
     }
 }
@@ -100,7 +266,8 @@ impl Modify for SecurityAddon {
     tags(
         (name = "Health", description = "Health check endpoints"),
         (name = "Auth", description = "Authentication endpoints"),
-        (name = "Orders", description = "Order management endpoints")
+        (name = "Orders", description = "Order management endpoints"),
+        (name = "Telemetry", description = "Client-side diagnostics")
     ),
     modifiers(&SecurityAddon)
 )]
@@ -111,28 +278,45 @@ async fn main() {
     // Load .env file if present
     dotenvy::dotenv().ok();
 
+    config::init_config();
+    let config = config::get_config();
+
+    // `--verbose-boot` logs each startup step, not just the final boot report
+    let mut filter = tracing_subscriber::EnvFilter::from_default_env();
+    if std::env::args().any(|arg| arg == "--verbose-boot") {
+        filter = filter.add_directive(
+            format!("{}=debug", BOOT_TARGET)
+                .parse()
+                .expect("boot target is a valid log directive"),
+        );
+    }
+    let pretty_logs = config.pretty_logs;
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .with(pretty_logs.then(|| tracing_subscriber::fmt::layer().pretty()))
+        .with((!pretty_logs).then(tracing_subscriber::fmt::layer))
+        .with(filter)
         .init();
+    tracing::debug!(target: BOOT_TARGET, "Using {:?} profile", config.profile);
 
     // Initialize JWT verifier with Cognito configuration
     let issuer = std::env::var("OIDC_ISSUER").expect("OIDC_ISSUER must be set");
     let client_id = std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID must be set");
-    JwksVerifier::init(issuer, client_id);
-    tracing::info!("JWT verifier initialized");
+    JwksVerifier::init(issuer.clone(), client_id);
+    tracing::debug!(target: BOOT_TARGET, "JWT verifier initialized for {}", issuer);
 
     // Initialize database connection
     db::init_db()
         .await
         .expect("Failed to connect to MongoDB");
+    tracing::debug!(target: BOOT_TARGET, "Database connected");
+    if recompute::requested() {
+        recompute::run_from_args().await.expect("Recompute failed");
+        return;
+    }
+    retention::spawn();
+    webhooks::spawn();
 
-    let cors = CorsLayer::new()
-        .allow_origin(AllowOrigin::mirror_request())
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::PATCH, Method::OPTIONS])
-        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT])
-        .expose_headers([header::CONTENT_TYPE])
-        .allow_credentials(true);
+    let cors = cors::cors_layer();
 
     // Rate limiting: 60 requests per minute per IP
     // Use SmartIpKeyExtractor to get IP from X-Forwarded-For header (required behind Fly.io proxy)
@@ -145,12 +329,23 @@ async fn main() {
     let rate_limit = GovernorLayer::new(governor_config);
 
     // Public routes (no auth required)
-    let public_routes = OpenApiRouter::new().routes(utoipa_axum::routes!(health));
+    let public_routes = OpenApiRouter::new()
+        .routes(utoipa_axum::routes!(health))
+        .routes(utoipa_axum::routes!(ready))
+        .routes(utoipa_axum::routes!(version))
+        .routes(utoipa_axum::routes!(status))
+        .merge(routes::telemetry::router())
+        .merge(routes::introspect::router())
+        .merge(routes::share::public_router().layer(middleware::from_fn(status_format::apply)));
 
     // Protected routes (auth middleware applied)
     let protected_routes = OpenApiRouter::new()
         .routes(utoipa_axum::routes!(me))
         .merge(routes::orders::router())
+        .merge(routes::images::router())
+        .merge(routes::share::router())
+        .layer(middleware::from_fn(list_cache::invalidate_on_write))
+        .layer(middleware::from_fn(status_format::apply))
         .layer(middleware::from_fn(auth_middleware));
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
@@ -158,31 +353,96 @@ async fn main() {
         .merge(protected_routes)
         .split_for_parts();
 
-    let enable_swagger = std::env::var("ENABLE_SWAGGER")
-        .map(|v| v == "true" || v == "1")
-        .unwrap_or(false);
+    // Debug builds log request bodies that drift from the documented schemas
+    #[cfg(debug_assertions)]
+    let router = {
+        schema_check::init(&api);
+        router.layer(middleware::from_fn(schema_check::check_request_schema))
+    };
+
+    let enable_swagger = config.enable_swagger;
+
+    let security_headers = middleware::from_fn(security_headers::security_headers);
 
     // CORS must be outermost (last) to handle preflight OPTIONS before rate limiting
     let app = if enable_swagger {
         router
-            .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", api))
+            .merge(api_docs::spec_router(&api))
+            .merge(SwaggerUi::new("/swagger-ui").config(utoipa_swagger_ui::Config::from(api_docs::SPEC_PATH)))
             .layer(rate_limit)
+            .layer(security_headers)
             .layer(cors)
     } else {
-        router.layer(rate_limit).layer(cors)
+        router.layer(rate_limit).layer(security_headers).layer(cors)
+    };
+
+    let app = if config.tolerate_trailing_slash {
+        routes::tolerate_trailing_slash(app)
+    } else {
+        app
+    };
+
+    // Outermost, so the logged latency and status cover every other layer
+    let app = if config.access_log {
+        app.layer(access_log::access_log_layer())
+    } else {
+        app
     };
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
+    let addr: SocketAddr = format!("0.0.0.0:{}", port)
+        .parse()
+        .expect("PORT must be a valid port number");
 
-    tracing::info!("Server running on {}", addr);
-    if enable_swagger {
-        tracing::info!(
-            "Swagger UI available at http://localhost:{}/swagger-ui",
-            port
-        );
+    // Peer address is the rate-limit key fallback when there is no proxy header
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let scheme = if config.tls.is_some() { "https" } else { "http" };
+    tracing::debug!(target: BOOT_TARGET, "Routes and middleware assembled");
+
+    match &config.tls {
+        Some(tls) => {
+            rustls::crypto::ring::default_provider()
+                .install_default()
+                .expect("Failed to install rustls crypto provider");
+            let tls_config = RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .expect("Failed to load TLS certificate/key");
+            tracing::debug!(target: BOOT_TARGET, "TLS certificate loaded from {}", tls.cert_path);
+            log_boot_report(scheme, addr, &issuer);
+            axum_server::bind_rustls(addr, tls_config)
+                .serve(service)
+                .await
+                .unwrap();
+        }
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            log_boot_report(scheme, addr, &issuer);
+            axum::serve(listener, service).await.unwrap();
+        }
     }
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// One structured summary of how the server started: where it listens and which optional
+/// features are on. Secrets (introspection secret, TLS key, database URI) are never logged.
+fn log_boot_report(scheme: &str, addr: SocketAddr, issuer: &str) {
+    let config = config::get_config();
+    tracing::info!(
+        target: BOOT_TARGET,
+        version = env!("CARGO_PKG_VERSION"),
+        git_sha = env!("GIT_SHA"),
+        profile = ?config.profile,
+        address = %format_args!("{}://{}", scheme, addr),
+        issuer,
+        swagger_ui = config.enable_swagger,
+        allowed_origins = ?config.allowed_origins,
+        access_log = config.access_log,
+        stream_order_list = config.stream_order_list,
+        list_cache_ttl = ?config.list_cache_ttl,
+        order_sources = config.order_sources,
+        order_retention_days = ?config.order_retention_days,
+        webhooks = config.webhook_urls.len(),
+        introspection = config.introspection_secret.is_some(),
+        db_read_preference = ?config.db_read_preference,
+        "Server ready"
+    );
 }
@@ -1,34 +1,125 @@
-use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use mongodb::bson::{self, doc, Document};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+use crate::auth::{Claims, Owner};
+use crate::dates::{days_since, normalize_order_date, now_rfc3339};
+use crate::status_format::{self, StatusFormat};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum OrderStatus {
+    #[default]
     Uncommented,
     Commented,
     CommentRevealed,
     Reimbursed,
 }
 
+impl OrderStatus {
+    /// Stored string form, matching the serde representation
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Uncommented => "uncommented",
+            OrderStatus::Commented => "commented",
+            OrderStatus::CommentRevealed => "comment_revealed",
+            OrderStatus::Reimbursed => "reimbursed",
+        }
+    }
+
+    /// Integer form for `statusFormat=code`. Assigned once and never reused or
+    /// renumbered; a new status gets the next free code.
+    pub fn code(&self) -> u8 {
+        match self {
+            OrderStatus::Uncommented => 0,
+            OrderStatus::Commented => 1,
+            OrderStatus::CommentRevealed => 2,
+            OrderStatus::Reimbursed => 3,
+        }
+    }
+
+    /// Fields (as named in `PATCH /orders/{id}`) a user may change while an order is in
+    /// this status; the single source for both `editableFields` and update enforcement.
+    /// `updatedAt`/`deletedAt` are sync bookkeeping and always writable.
+    pub fn editable_fields(&self) -> &'static [&'static str] {
+        match self {
+            OrderStatus::Reimbursed => &["note", "tags"],
+            OrderStatus::Uncommented | OrderStatus::Commented | OrderStatus::CommentRevealed => {
+                &["status", "note", "tags"]
+            }
+        }
+    }
+
+    /// Entity field recording when an order last moved into this status, if it is tracked
+    pub fn reached_at_field(&self) -> Option<&'static str> {
+        match self {
+            OrderStatus::Commented => Some("commented_at"),
+            OrderStatus::CommentRevealed => Some("revealed_at"),
+            OrderStatus::Uncommented | OrderStatus::Reimbursed => None,
+        }
+    }
+}
+
+/// Trim and lowercase tags, dropping blanks and duplicates while keeping first-seen order
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
 /// Internal database entity - stored with snake_case field names in MongoDB
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderEntity {
     pub id: String,
     pub user_id: String,
+    /// Set under `MULTI_TENANT`; part of every ownership filter (see `auth::Owner`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
     pub order_number: String,
+    /// Marketplace/account the order number belongs to; part of the upsert key when
+    /// `ORDER_SOURCES` is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
     pub product_name: String,
     pub order_date: String,
+    /// `order_date` normalized to `YYYY-MM-DD`; absent when it could not be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_date_iso: Option<String>,
     pub product_image: String,
     pub price: String,
     pub status: OrderStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
+    /// Normalized (trimmed, lowercase, unique) user labels
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deleted_at: Option<String>,
+    /// Set when the status last moved to `commented`; kept when it moves on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commented_at: Option<String>,
+    /// Set when the status last moved to `comment_revealed`; kept when it moves on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revealed_at: Option<String>,
+    /// Hidden from the default order list without being deleted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// Server time of the last write, for `updatedSince` delta sync (unlike client-sent `updated_at`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<bson::DateTime>,
+    /// Bumped to revoke the order's share links; links made for another version are invalid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_version: Option<u32>,
 }
 
 /// API response type - serialized with camelCase for frontend
@@ -36,60 +127,223 @@ pub struct OrderEntity {
 #[serde(rename_all = "camelCase")]
 pub struct Order {
     pub id: String,
+    /// Always the caller's own id
     pub user_id: String,
     #[schema(example = "123-4567890-1234567")]
     pub order_number: String,
+    /// Marketplace/account the order came from, if the client sent one
+    #[schema(example = "amazon.de")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
     #[schema(example = "Wireless Bluetooth Headphones")]
     pub product_name: String,
     #[schema(example = "December 25, 2024")]
     pub order_date: String,
+    /// `orderDate` normalized to `YYYY-MM-DD`, when it could be parsed
+    #[schema(example = "2024-12-25")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_date_iso: Option<String>,
     pub product_image: String,
     #[schema(example = "$29.99")]
     pub price: String,
+    /// Integer code instead with `statusFormat=code`
+    #[serde(serialize_with = "status_format::serialize")]
     pub status: OrderStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
+    /// User labels, lowercase
+    #[schema(example = json!(["gift", "work"]))]
+    pub tags: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub deleted_at: Option<String>,
+    /// When the order was last marked as commented
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commented_at: Option<String>,
+    /// When the order's comment was last marked as revealed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revealed_at: Option<String>,
+    /// Hidden from `GET /orders` unless `includeArchived=true`
+    pub archived: bool,
+    /// Whole days since `orderDateIso` (UTC); null when the order date is unknown
+    #[schema(example = 12)]
+    pub days_since_order: Option<i64>,
+    /// Coarse age for display; null when the order date is unknown
+    pub age_bucket: Option<AgeBucket>,
+    /// Fields `PATCH /orders/{id}` accepts changes to in the order's current status
+    #[schema(value_type = Vec<String>, example = json!(["status", "note", "tags"]))]
+    pub editable_fields: &'static [&'static str],
+}
+
+/// Orders up to this many days old are `recent`
+const RECENT_ORDER_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AgeBucket {
+    /// Ordered within the last 30 days
+    Recent,
+    Old,
+}
+
+impl AgeBucket {
+    fn from_days(days: i64) -> Self {
+        if days <= RECENT_ORDER_DAYS { AgeBucket::Recent } else { AgeBucket::Old }
+    }
+}
+
+impl Order {
+    /// Strong ETag over the stored fields, for `If-Match` conditional writes. The
+    /// age fields are left out: they change daily without the order changing. Always
+    /// hashed with the string status, so `statusFormat` does not change the tag.
+    pub fn etag(&self) -> String {
+        let stored = Order { days_since_order: None, age_bucket: None, ..self.clone() };
+        let json = status_format::scoped(StatusFormat::String, || serde_json::to_vec(&stored))
+            .expect("Order serializes to JSON");
+        let digest = Sha256::digest(&json);
+        let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("\"{}\"", hex)
+    }
 }
 
 impl From<OrderEntity> for Order {
     fn from(e: OrderEntity) -> Self {
+        let days_since_order = e.order_date_iso.as_deref().and_then(days_since);
         Self {
             id: e.id,
             user_id: e.user_id,
             order_number: e.order_number,
+            source: e.source,
             product_name: e.product_name,
             order_date: e.order_date,
+            order_date_iso: e.order_date_iso,
             product_image: e.product_image,
             price: e.price,
-            status: e.status,
             note: e.note,
+            tags: e.tags,
             updated_at: e.updated_at,
             created_at: e.created_at,
             deleted_at: e.deleted_at,
+            commented_at: e.commented_at,
+            revealed_at: e.revealed_at,
+            archived: e.archived,
+            days_since_order,
+            age_bucket: days_since_order.map(AgeBucket::from_days),
+            editable_fields: e.status.editable_fields(),
+            status: e.status,
         }
     }
 }
 
+/// `shape=nested` form of an order, grouped the way the frontend displays it. Carries a
+/// subset of the fields and no `deletedAt`, so `GET /orders` refuses it with `updatedSince`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NestedOrder {
+    pub id: String,
+    pub product: NestedOrderProduct,
+    pub meta: NestedOrderMeta,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NestedOrderProduct {
+    #[schema(example = "Wireless Bluetooth Headphones")]
+    pub name: String,
+    pub image: String,
+    #[schema(example = "123-4567890-1234567")]
+    pub order_number: String,
+    #[schema(example = "$29.99")]
+    pub price: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NestedOrderMeta {
+    /// Integer code instead with `statusFormat=code`
+    #[serde(serialize_with = "status_format::serialize")]
+    pub status: OrderStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+impl From<Order> for NestedOrder {
+    fn from(o: Order) -> Self {
+        Self {
+            id: o.id,
+            product: NestedOrderProduct {
+                name: o.product_name,
+                image: o.product_image,
+                order_number: o.order_number,
+                price: o.price,
+            },
+            meta: NestedOrderMeta {
+                status: o.status,
+                note: o.note,
+                created_at: o.created_at,
+                updated_at: o.updated_at,
+            },
+        }
+    }
+}
+
+/// An order in the shape the client asked for (`shape`)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum OrderBody {
+    Flat(Box<Order>),
+    Nested(NestedOrder),
+}
+
+/// A request field that is accepted and thrown away. Request bodies take `userId` as one,
+/// so clients can send back whole orders while handlers have no value to read; orders
+/// always belong to the authenticated user. Unlike `serde::de::IgnoredAny` it is read as a
+/// value, so `STRICT_JSON_FIELDS` does not report it as an unknown field.
+#[derive(Debug, Default)]
+pub struct Discarded;
+
+impl<'de> Deserialize<'de> for Discarded {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde_json::Value::deserialize(deserializer).map(|_| Discarded)
+    }
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateOrderRequest {
+    /// Ignored: orders always belong to the authenticated user
+    #[allow(dead_code)]
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub user_id: Discarded,
     pub id: String,
     pub order_number: String,
+    /// Marketplace/account the order number belongs to (e.g. `amazon.de`). With
+    /// `ORDER_SOURCES` on, the same number may exist once per source; kept when omitted.
+    #[serde(default)]
+    pub source: Option<String>,
     pub product_name: String,
     pub order_date: String,
     pub product_image: String,
     pub price: String,
+    /// Defaults to `uncommented`
+    #[serde(default)]
+    #[schema(default = "uncommented")]
     pub status: OrderStatus,
     #[serde(default)]
     pub note: Option<String>,
+    /// Labels; trimmed and lowercased, duplicates dropped
+    #[serde(default)]
+    pub tags: Vec<String>,
     #[serde(default)]
     pub updated_at: Option<String>,
+    /// Kept when the order already exists; defaults to the server time when it is created
     #[serde(default)]
     pub created_at: Option<String>,
     #[serde(default)]
@@ -97,17 +351,27 @@ pub struct CreateOrderRequest {
 }
 
 impl CreateOrderRequest {
-    pub fn into_entity(self, user_id: String) -> OrderEntity {
+    pub fn into_entity(self, owner: &Owner) -> OrderEntity {
+        let reached_at = |status: OrderStatus| (self.status == status).then(now_rfc3339);
         OrderEntity {
+            commented_at: reached_at(OrderStatus::Commented),
+            revealed_at: reached_at(OrderStatus::CommentRevealed),
+            archived: false,
+            modified_at: Some(bson::DateTime::now()),
+            share_version: None,
             id: self.id,
-            user_id,
+            user_id: owner.user_id.clone(),
+            tenant_id: owner.tenant_id.clone(),
             order_number: self.order_number,
+            source: self.source,
             product_name: self.product_name,
+            order_date_iso: normalize_order_date(&self.order_date),
             order_date: self.order_date,
             product_image: self.product_image,
             price: self.price,
             status: self.status,
             note: self.note,
+            tags: normalize_tags(&self.tags),
             updated_at: self.updated_at,
             created_at: self.created_at,
             deleted_at: self.deleted_at,
@@ -115,15 +379,131 @@ impl CreateOrderRequest {
     }
 }
 
+#[cfg(test)]
+impl CreateOrderRequest {
+    /// JSON body of a valid order, with `overrides` replacing or adding fields
+    pub fn fixture_json(overrides: serde_json::Value) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "id": "order-1",
+            "orderNumber": "123-4567890-1234567",
+            "productName": "Headphones",
+            "orderDate": "December 25, 2024",
+            "productImage": "",
+            "price": "$29.99",
+            "status": "uncommented",
+        });
+        body.as_object_mut().unwrap().extend(overrides.as_object().expect("overrides must be an object").clone());
+        body
+    }
+
+    pub fn fixture(overrides: serde_json::Value) -> Self {
+        serde_json::from_value(Self::fixture_json(overrides)).unwrap()
+    }
+}
+
+/// Body for `PUT /orders/by-number/{orderNumber}`; the order number comes from the path
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpsertOrderRequest {
+    /// Ignored: orders always belong to the authenticated user
+    #[allow(dead_code)]
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub user_id: Discarded,
+    /// Used only when the order is created; generated if omitted
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Marketplace/account the order number belongs to; with `ORDER_SOURCES` on it
+    /// selects which order of that number is upserted. Kept when omitted.
+    #[serde(default)]
+    pub source: Option<String>,
+    pub product_name: String,
+    pub order_date: String,
+    pub product_image: String,
+    pub price: String,
+    /// Defaults to `uncommented`
+    #[serde(default)]
+    #[schema(default = "uncommented")]
+    pub status: OrderStatus,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Labels; trimmed and lowercased, duplicates dropped
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    /// Used only when the order is created; defaults to the server time
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateOrderRequest {
+    /// Ignored: orders always belong to the authenticated user
+    #[allow(dead_code)]
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub user_id: Discarded,
     pub status: Option<OrderStatus>,
     pub note: Option<String>,
+    /// Replaces the order's labels; `[]` clears them
+    pub tags: Option<Vec<String>>,
     pub updated_at: Option<String>,
     pub deleted_at: Option<String>,
 }
 
+impl UpdateOrderRequest {
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none()
+            && self.note.is_none()
+            && self.tags.is_none()
+            && self.updated_at.is_none()
+            && self.deleted_at.is_none()
+    }
+
+    /// `$set` document holding only the fields that differ from the stored order
+    pub fn changes_from(&self, current: &OrderEntity) -> Document {
+        let mut changes = doc! {};
+
+        if let Some(status) = self.status.as_ref().filter(|s| **s != current.status) {
+            changes.insert("status", status.as_str());
+            if let Some(field) = status.reached_at_field() {
+                changes.insert(field, now_rfc3339());
+            }
+        }
+        if let Some(note) = self.note.as_ref().filter(|n| current.note.as_ref() != Some(n)) {
+            changes.insert("note", note);
+        }
+        if let Some(tags) = self
+            .tags
+            .as_ref()
+            .map(|t| normalize_tags(t))
+            .filter(|t| *t != current.tags)
+        {
+            changes.insert("tags", tags);
+        }
+        if let Some(updated_at) = self
+            .updated_at
+            .as_ref()
+            .filter(|u| current.updated_at.as_ref() != Some(u))
+        {
+            changes.insert("updated_at", updated_at);
+        }
+        if let Some(deleted_at) = self
+            .deleted_at
+            .as_ref()
+            .filter(|d| current.deleted_at.as_ref() != Some(d))
+        {
+            changes.insert("deleted_at", deleted_at);
+        }
+
+        changes
+    }
+}
+
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BatchUpsertRequest {
@@ -133,7 +513,120 @@ pub struct BatchUpsertRequest {
 #[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BatchUpsertResponse {
+    /// Orders written (created + modified)
     pub upserted: usize,
+    /// Orders that did not exist before
+    pub created: usize,
+    /// Existing orders that were changed
+    pub modified: usize,
+    /// Orders that could not be written (best-effort mode only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<BatchItemError>,
+}
+
+/// What `POST /orders/batch` would do with one order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Create,
+    Update,
+    Unchanged,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    /// Field as named in the order JSON
+    #[schema(example = "status")]
+    pub field: &'static str,
+    /// Stored value; null when unset
+    #[schema(value_type = Object)]
+    pub from: serde_json::Value,
+    /// Value the import would write; null when it would be cleared
+    #[schema(value_type = Object)]
+    pub to: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreviewItem {
+    /// Position of the order in the request's `orders` array
+    pub index: usize,
+    pub order_number: String,
+    pub action: ImportAction,
+    /// Fields that would change (updates only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportPreview {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub items: Vec<ImportPreviewItem>,
+}
+
+impl OrderEntity {
+    /// Fields an import upsert of `incoming` would change on this stored order, matching
+    /// what [`CreateOrderRequest::into_entity`] plus the upsert write: omitted optional
+    /// fields are cleared, except `createdAt` and `source`, which are kept
+    pub fn import_changes(&self, incoming: &OrderEntity) -> Vec<FieldChange> {
+        fn json<T: Serialize>(value: &T) -> serde_json::Value {
+            serde_json::to_value(value).unwrap_or_default()
+        }
+
+        let created_at = incoming.created_at.as_ref().or(self.created_at.as_ref());
+        let source = incoming.source.as_ref().or(self.source.as_ref());
+        let fields = [
+            ("source", json(&self.source), json(&source)),
+            ("productName", json(&self.product_name), json(&incoming.product_name)),
+            ("orderDate", json(&self.order_date), json(&incoming.order_date)),
+            ("productImage", json(&self.product_image), json(&incoming.product_image)),
+            ("price", json(&self.price), json(&incoming.price)),
+            ("status", json(&self.status), json(&incoming.status)),
+            ("note", json(&self.note), json(&incoming.note)),
+            ("tags", json(&self.tags), json(&incoming.tags)),
+            ("updatedAt", json(&self.updated_at), json(&incoming.updated_at)),
+            ("createdAt", json(&self.created_at), json(&created_at)),
+            ("deletedAt", json(&self.deleted_at), json(&incoming.deleted_at)),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, from, to)| from != to)
+            .map(|(field, from, to)| FieldChange { field, from, to })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchItemError {
+    /// Position of the order in the request's `orders` array
+    pub index: usize,
+    pub order_number: String,
+    pub message: String,
+}
+
+/// How `POST /orders/batch` handles orders that fail to write
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchMode {
+    /// All orders are written in one transaction, or none are
+    Atomic,
+    /// Every order that can be written is; failures are reported per item
+    #[default]
+    BestEffort,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchUpsertQuery {
+    /// `atomic` (all-or-nothing) or `best-effort` (default)
+    #[serde(default)]
+    #[param(inline)]
+    pub mode: BatchMode,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -147,3 +640,351 @@ pub struct BatchDeleteRequest {
 pub struct BatchDeleteResponse {
     pub deleted: usize,
 }
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchGetRequest {
+    /// Order IDs to fetch; duplicates are returned once
+    pub ids: Vec<String>,
+}
+
+/// Field `GET /orders/grouped` groups by
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Status,
+    /// Month of the normalized order date (`YYYY-MM`), newest first
+    Month,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GroupOrdersQuery {
+    /// `status` or `month`
+    #[param(inline)]
+    pub by: GroupBy,
+    /// Most orders returned per group, newest first (server default and maximum page size apply)
+    #[param(value_type = Option<u32>, minimum = 1)]
+    pub limit: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderGroup {
+    /// Status, or `YYYY-MM` month; null groups orders whose date could not be parsed
+    #[schema(example = "2024-12")]
+    pub key: Option<String>,
+    /// Orders in the group, which may exceed `orders.len()`
+    pub count: u64,
+    pub orders: Vec<OrderBody>,
+}
+
+/// Number of non-deleted orders in each status; every status is always present
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusCounts {
+    pub uncommented: u64,
+    pub commented: u64,
+    pub comment_revealed: u64,
+    pub reimbursed: u64,
+}
+
+impl StatusCounts {
+    pub fn add(&mut self, status: &OrderStatus, count: u64) {
+        let slot = match status {
+            OrderStatus::Uncommented => &mut self.uncommented,
+            OrderStatus::Commented => &mut self.commented,
+            OrderStatus::CommentRevealed => &mut self.comment_revealed,
+            OrderStatus::Reimbursed => &mut self.reimbursed,
+        };
+        *slot += count;
+    }
+}
+
+/// Storage the user's data takes up, for quota displays
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsage {
+    /// Stored orders, including archived and soft-deleted ones
+    pub order_count: u64,
+    /// Approximate total BSON size of those orders, in bytes
+    pub order_bytes: u64,
+    /// Uploaded product images
+    pub image_count: u64,
+    /// Total size of uploaded product images, in bytes
+    pub image_bytes: u64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListOrdersQuery {
+    /// Earliest order date to include (`YYYY-MM-DD`, inclusive)
+    pub from: Option<String>,
+    /// Latest order date to include (`YYYY-MM-DD`, inclusive)
+    pub to: Option<String>,
+    /// Include archived orders (excluded by default)
+    pub include_archived: Option<bool>,
+    /// Only orders written after this RFC 3339 time, soft-deleted ones included
+    pub updated_since: Option<String>,
+    /// Only orders carrying this tag; repeat to require several (`?tag=gift&tag=work`)
+    #[serde(default)]
+    pub tag: Vec<String>,
+    /// `shape` from [`ShapeParams`], read here too so it is checked with `updatedSince`
+    #[param(ignore)]
+    pub shape: Option<OrderShape>,
+}
+
+/// Placeholder parameter shared by endpoints that read orders
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct PlaceholderParams {
+    /// Fill an empty `productImage` with the server's placeholder image, when one is
+    /// configured (default `true`); `false` returns the stored value
+    pub with_placeholders: Option<bool>,
+}
+
+/// Response shape of an order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderShape {
+    /// Every field at the top level (default)
+    #[default]
+    Flat,
+    /// `{ id, product: {...}, meta: {...} }`, see `NestedOrder`
+    Nested,
+}
+
+/// Shape parameter shared by endpoints that read orders
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ShapeParams {
+    /// `nested` groups product fields and metadata (`NestedOrder`); defaults to `flat`
+    pub shape: Option<OrderShape>,
+}
+
+/// Cursor pagination parameters shared by list endpoints
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Items per page (server default and maximum apply)
+    #[param(value_type = Option<u32>, minimum = 1)]
+    pub limit: Option<String>,
+    /// Opaque cursor from the previous page's `X-Next-Cursor` header
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLink {
+    /// Link to hand out: `SHARE_LINK_BASE_URL` followed by `/orders/shared/{token}`
+    pub url: String,
+    pub token: String,
+    /// When the link stops working (RFC 3339)
+    pub expires_at: String,
+}
+
+/// Read-only view of a shared order; the owner's id, notes and tags stay private
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedOrder {
+    #[schema(example = "123-4567890-1234567")]
+    pub order_number: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[schema(example = "Wireless Bluetooth Headphones")]
+    pub product_name: String,
+    #[schema(example = "December 25, 2024")]
+    pub order_date: String,
+    /// Remote image URL; uploaded images need the owner's token and are left out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_image: Option<String>,
+    #[schema(example = "$29.99")]
+    pub price: String,
+    /// Integer code instead with `statusFormat=code`
+    #[serde(serialize_with = "status_format::serialize")]
+    pub status: OrderStatus,
+}
+
+impl From<OrderEntity> for SharedOrder {
+    fn from(e: OrderEntity) -> Self {
+        let remote = e.product_image.starts_with("https://") || e.product_image.starts_with("http://");
+        Self {
+            order_number: e.order_number,
+            source: e.source,
+            product_name: e.product_name,
+            order_date: e.order_date,
+            product_image: remote.then_some(e.product_image),
+            price: e.price,
+            status: e.status,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RekeyOrderRequest {
+    /// Replacement order ID; no other order of the user may have it
+    #[schema(example = "order-2024-0042")]
+    pub new_id: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateImageRequest {
+    /// Remote image URL (http or https)
+    #[schema(example = "https://m.media-amazon.com/images/I/61abc.jpg")]
+    pub url: String,
+}
+
+/// Multipart body for image uploads (documentation only)
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct ImageUpload {
+    /// PNG, JPEG, WebP or GIF image file
+    #[schema(value_type = String, format = Binary)]
+    pub image: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Diagnostic event reported by the extension or web app
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryEvent {
+    pub level: TelemetryLevel,
+    #[schema(example = "Failed to scrape order page")]
+    pub message: String,
+    /// Free-form JSON object with extra details (page, version, stack, ...)
+    #[serde(default)]
+    #[schema(value_type = Option<Object>, example = json!({ "page": "order-history", "version": "1.0.10" }))]
+    pub context: Option<serde_json::Value>,
+}
+
+/// RFC 7662 introspection request; other parameters such as `token_type_hint` are ignored
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct IntrospectionRequest {
+    /// Access token to check
+    pub token: String,
+}
+
+/// RFC 7662 introspection response; only `active` is present for inactive tokens
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    /// User subject (unique identifier)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// Cognito username
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Tenant the user belongs to, under `MULTI_TENANT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// Expiry, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    /// Issue time, in seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
+
+impl IntrospectionResponse {
+    pub fn active(claims: Claims) -> Self {
+        Self {
+            active: true,
+            sub: Some(claims.sub),
+            username: claims.username,
+            email: claims.email,
+            tenant_id: claims.tenant_id,
+            exp: claims.exp,
+            iat: claims.iat,
+            iss: claims.iss,
+            token_type: Some("Bearer".to_string()),
+        }
+    }
+
+    pub fn inactive() -> Self {
+        Self {
+            active: false,
+            sub: None,
+            username: None,
+            email: None,
+            tenant_id: None,
+            exp: None,
+            iat: None,
+            iss: None,
+            token_type: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn stored() -> OrderEntity {
+        serde_json::from_value(json!({
+            "id": "order-1",
+            "user_id": "user-1",
+            "order_number": "123-4567890-1234567",
+            "product_name": "Headphones",
+            "order_date": "December 25, 2024",
+            "product_image": "",
+            "price": "$29.99",
+            "status": "commented",
+            "note": "gift",
+            "tags": ["gift", "audio"],
+            "updated_at": "2024-12-26T10:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn patch(body: serde_json::Value) -> UpdateOrderRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn resending_the_stored_values_changes_nothing() {
+        let request = patch(json!({
+            "status": "commented",
+            "note": "gift",
+            "tags": [" Gift ", "AUDIO", "gift"],
+            "updatedAt": "2024-12-26T10:00:00Z",
+        }));
+        assert!(!request.is_empty());
+        assert_eq!(request.changes_from(&stored()), doc! {});
+    }
+
+    #[test]
+    fn only_fields_that_differ_are_written() {
+        let changes = patch(json!({ "status": "commented", "note": "birthday gift" })).changes_from(&stored());
+        assert_eq!(changes, doc! { "note": "birthday gift" });
+
+        // A new status also stamps when it was reached
+        let changes = patch(json!({ "status": "comment_revealed", "note": "gift" })).changes_from(&stored());
+        assert_eq!(changes.keys().collect::<Vec<_>>(), ["status", "revealed_at"]);
+        assert_eq!(changes.get_str("status"), Ok("comment_revealed"));
+    }
+
+    #[test]
+    fn reordered_tags_are_a_change() {
+        let changes = patch(json!({ "tags": ["audio", "gift"] })).changes_from(&stored());
+        assert_eq!(changes, doc! { "tags": ["audio", "gift"] });
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::config::get_config;
+use crate::models::{BatchUpsertRequest, CreateOrderRequest, RekeyOrderRequest, UpdateOrderRequest, UpsertOrderRequest};
+
+/// How much whitespace is removed from a string field before it is validated and stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whitespace {
+    /// Stored exactly as sent
+    Keep,
+    /// Leading and trailing whitespace removed
+    Trim,
+    /// Trimmed, and every internal run of whitespace replaced by one space
+    Collapse,
+}
+
+impl FromStr for Whitespace {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Whitespace::Keep),
+            "trim" => Ok(Whitespace::Trim),
+            "collapse" => Ok(Whitespace::Collapse),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Whitespace {
+    fn apply(self, value: &mut String) {
+        match self {
+            Whitespace::Keep => {}
+            Whitespace::Trim => *value = value.trim().to_string(),
+            Whitespace::Collapse => *value = value.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// Request fields that are normalized, with their default treatment. Notes keep their
+/// line breaks, so they are only trimmed.
+const DEFAULT_FIELDS: &[(&str, Whitespace)] = &[
+    ("id", Whitespace::Trim),
+    ("orderNumber", Whitespace::Collapse),
+    ("source", Whitespace::Trim),
+    ("productName", Whitespace::Collapse),
+    ("orderDate", Whitespace::Trim),
+    ("productImage", Whitespace::Trim),
+    ("price", Whitespace::Trim),
+    ("note", Whitespace::Trim),
+    ("updatedAt", Whitespace::Trim),
+    ("createdAt", Whitespace::Trim),
+    ("deletedAt", Whitespace::Trim),
+];
+
+/// Whitespace treatment per request field (camelCase, as in the JSON body)
+#[derive(Debug)]
+pub struct StringNormalization(BTreeMap<&'static str, Whitespace>);
+
+impl Default for StringNormalization {
+    fn default() -> Self {
+        Self(DEFAULT_FIELDS.iter().copied().collect())
+    }
+}
+
+impl StringNormalization {
+    /// Override the default for one field; `false` if the field is not normalized at all
+    pub fn set(&mut self, field: &str, mode: Whitespace) -> bool {
+        self.0.get_mut(field).map(|m| *m = mode).is_some()
+    }
+
+    pub fn apply(&self, field: &str, value: &mut String) {
+        self.0.get(field).copied().unwrap_or(Whitespace::Keep).apply(value);
+    }
+
+    fn apply_opt(&self, field: &str, value: &mut Option<String>) {
+        if let Some(value) = value {
+            self.apply(field, value);
+        }
+    }
+}
+
+/// Request bodies whose string fields are cleaned up before validation, so `" 123 "`
+/// and `"123"` are stored (and matched) as the same order number
+pub trait Normalize {
+    fn normalize(&mut self);
+}
+
+impl Normalize for CreateOrderRequest {
+    fn normalize(&mut self) {
+        let rules = &get_config().string_normalization;
+        rules.apply("id", &mut self.id);
+        rules.apply("orderNumber", &mut self.order_number);
+        rules.apply_opt("source", &mut self.source);
+        rules.apply("productName", &mut self.product_name);
+        rules.apply("orderDate", &mut self.order_date);
+        rules.apply("productImage", &mut self.product_image);
+        rules.apply("price", &mut self.price);
+        rules.apply_opt("note", &mut self.note);
+        rules.apply_opt("updatedAt", &mut self.updated_at);
+        rules.apply_opt("createdAt", &mut self.created_at);
+        rules.apply_opt("deletedAt", &mut self.deleted_at);
+    }
+}
+
+impl Normalize for UpsertOrderRequest {
+    fn normalize(&mut self) {
+        let rules = &get_config().string_normalization;
+        rules.apply_opt("id", &mut self.id);
+        rules.apply_opt("source", &mut self.source);
+        rules.apply("productName", &mut self.product_name);
+        rules.apply("orderDate", &mut self.order_date);
+        rules.apply("productImage", &mut self.product_image);
+        rules.apply("price", &mut self.price);
+        rules.apply_opt("note", &mut self.note);
+        rules.apply_opt("updatedAt", &mut self.updated_at);
+        rules.apply_opt("createdAt", &mut self.created_at);
+        rules.apply_opt("deletedAt", &mut self.deleted_at);
+    }
+}
+
+impl Normalize for UpdateOrderRequest {
+    fn normalize(&mut self) {
+        let rules = &get_config().string_normalization;
+        rules.apply_opt("note", &mut self.note);
+        rules.apply_opt("updatedAt", &mut self.updated_at);
+        rules.apply_opt("deletedAt", &mut self.deleted_at);
+    }
+}
+
+impl Normalize for RekeyOrderRequest {
+    fn normalize(&mut self) {
+        get_config().string_normalization.apply("id", &mut self.new_id);
+    }
+}
+
+impl Normalize for BatchUpsertRequest {
+    fn normalize(&mut self) {
+        self.orders.iter_mut().for_each(Normalize::normalize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn create_request(order_number: &str) -> CreateOrderRequest {
+        CreateOrderRequest::fixture(json!({
+            "id": " order-1 ",
+            "orderNumber": order_number,
+            "productName": "  Wireless   Headphones ",
+            "price": " $29.99 ",
+            "note": "  line one\n  line two  ",
+        }))
+    }
+
+    fn normalized(order_number: &str) -> CreateOrderRequest {
+        crate::config::init_test_config();
+        let mut request = create_request(order_number);
+        request.normalize();
+        request
+    }
+
+    #[test]
+    fn padded_order_numbers_match_the_plain_ones() {
+        assert_eq!(normalized(" 123 ").order_number, "123");
+        assert_eq!(normalized("\t123\n").order_number, normalized("123").order_number);
+        assert_eq!(normalized("123  -  456").order_number, "123 - 456");
+
+        let mut batch = BatchUpsertRequest { orders: vec![create_request(" 123 "), create_request("123")] };
+        batch.normalize();
+        assert_eq!(batch.orders[0].order_number, batch.orders[1].order_number);
+    }
+
+    #[test]
+    fn default_rules_per_field() {
+        let request = normalized("123");
+        assert_eq!(request.id, "order-1");
+        assert_eq!(request.product_name, "Wireless Headphones");
+        assert_eq!(request.price, "$29.99");
+        // Notes are only trimmed, so their line breaks survive
+        assert_eq!(request.note.as_deref(), Some("line one\n  line two"));
+    }
+
+    #[test]
+    fn modes_can_be_overridden_per_known_field() {
+        let mut rules = StringNormalization::default();
+        assert!(rules.set("orderNumber", Whitespace::Keep));
+        assert!(!rules.set("userId", Whitespace::Trim));
+
+        let mut value = " 123 ".to_string();
+        rules.apply("orderNumber", &mut value);
+        assert_eq!(value, " 123 ");
+        // Fields without a rule are kept as sent
+        rules.apply("status", &mut value);
+        assert_eq!(value, " 123 ");
+    }
+}
@@ -0,0 +1,132 @@
+use printpdf::{
+    image_crate, BuiltinFont, Image, ImageTransform, Mm, PdfDocument, PdfLayerReference,
+};
+
+use crate::models::Order;
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const LINE_HEIGHT: f32 = 6.0;
+/// Characters per wrapped line at the body font size; Helvetica has no metrics here
+const WRAP_COLUMNS: usize = 85;
+/// Largest box the product image is scaled to fit, in millimetres
+const MAX_IMAGE_MM: f32 = 90.0;
+/// Below this much free space the image is left out rather than shrunk further
+const MIN_IMAGE_MM: f32 = 20.0;
+/// Resolution images are placed at before scaling; only affects the unscaled size
+const IMAGE_DPI: f32 = 300.0;
+
+/// Render one order as a single A4 page. The product image is drawn when it decodes;
+/// otherwise the page notes that it is unavailable. Text uses the built-in Helvetica,
+/// so characters outside Windows-1252 do not render.
+pub fn render_order(order: &Order, image: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let title = format!("Order {}", order.order_number);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Order");
+    let layer = doc.get_page(page).get_layer(layer);
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| e.to_string())?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(|e| e.to_string())?;
+
+    let mut y = PAGE_HEIGHT - MARGIN;
+    layer.use_text(&title, 18.0, Mm(MARGIN), Mm(y), &bold);
+    y -= LINE_HEIGHT * 2.0;
+
+    let status = serde_json::to_value(&order.status)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default();
+    let mut fields = vec![
+        ("Product", order.product_name.clone()),
+        ("Order date", order.order_date.clone()),
+        ("Price", order.price.clone()),
+        ("Status", status),
+    ];
+    if !order.tags.is_empty() {
+        fields.push(("Tags", order.tags.join(", ")));
+    }
+    if let Some(note) = order.note.as_deref().filter(|n| !n.trim().is_empty()) {
+        fields.push(("Note", note.to_string()));
+    }
+    if order.archived {
+        fields.push(("Archived", "yes".to_string()));
+    }
+
+    for (label, value) in fields {
+        layer.use_text(label, 10.0, Mm(MARGIN), Mm(y), &bold);
+        y -= LINE_HEIGHT;
+        for line in wrap(&value, WRAP_COLUMNS) {
+            layer.use_text(line, 11.0, Mm(MARGIN), Mm(y), &regular);
+            y -= LINE_HEIGHT;
+        }
+        y -= LINE_HEIGHT / 2.0;
+    }
+
+    y -= LINE_HEIGHT;
+    let image_box = (y - MARGIN).min(MAX_IMAGE_MM);
+    let decoded = image
+        .filter(|_| image_box >= MIN_IMAGE_MM)
+        .and_then(|bytes| image_crate::load_from_memory(bytes).ok());
+    match decoded {
+        Some(decoded) => draw_image(&layer, &decoded, y, image_box),
+        None => layer.use_text("Product image unavailable", 10.0, Mm(MARGIN), Mm(y), &regular),
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+/// Place an image with its top edge at `top`, scaled down to fit a square box
+fn draw_image(layer: &PdfLayerReference, decoded: &image_crate::DynamicImage, top: f32, size: f32) {
+    let px_to_mm = 25.4 / IMAGE_DPI;
+    let (width, height) = (decoded.width() as f32 * px_to_mm, decoded.height() as f32 * px_to_mm);
+    let scale = (size / width).min(size / height).min(1.0);
+    let height = height * scale;
+
+    Image::from_dynamic_image(decoded).add_to_layer(
+        layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(top - height)),
+            scale_x: Some(scale),
+            scale_y: Some(scale),
+            dpi: Some(IMAGE_DPI),
+            ..Default::default()
+        },
+    );
+}
+
+/// Greedy word wrap; words longer than a line are split
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while word.len() > columns {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                lines.push(word.drain(..columns).collect());
+            }
+            let word: String = word.into_iter().collect();
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > columns {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// `Content-Disposition` filename for an order's PDF, limited to safe characters
+pub fn file_name(order: &Order) -> String {
+    let number: String = order
+        .order_number
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    format!("order-{}.pdf", if number.is_empty() { "export" } else { &number })
+}
@@ -0,0 +1,124 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::UpdateOneModel,
+};
+
+use crate::config::get_config;
+use crate::dates::normalize_order_date;
+use crate::db::{get_client, orders_collection};
+use crate::models::normalize_tags;
+
+/// Tracing target for backfill progress (`RUST_LOG=order_wizard::recompute=info`)
+const TARGET: &str = "order_wizard::recompute";
+
+/// `server recompute [--after <_id>]` backfills derived fields and exits instead of serving
+pub fn requested() -> bool {
+    std::env::args().nth(1).as_deref() == Some("recompute")
+}
+
+/// Recompute the fields derived from what clients send (`order_date_iso`, normalized
+/// `tags`) for every stored order, in `_id` order and `BULK_WRITE_CHUNK_SIZE` batches.
+/// Only orders whose derived values changed are written, so a re-run is harmless; after
+/// an interruption, `--after` with the last logged `_id` skips the orders already done.
+pub async fn run_from_args() -> Result<(), mongodb::error::Error> {
+    let args: Vec<String> = std::env::args().collect();
+    let after = args
+        .iter()
+        .position(|arg| arg == "--after")
+        .map(|i| args.get(i + 1).expect("--after needs an order _id"))
+        .map(|id| ObjectId::parse_str(id).expect("--after must be an order _id (ObjectId hex)"));
+
+    let collection = orders_collection();
+    let filter = match after {
+        Some(id) => doc! { "_id": { "$gt": id } },
+        None => doc! {},
+    };
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(filter)
+        .projection(doc! { "order_date": 1, "order_date_iso": 1, "tags": 1 })
+        .sort(doc! { "_id": 1 })
+        .await?;
+
+    let chunk_size = get_config().bulk_write_chunk_size;
+    let mut updates = Vec::with_capacity(chunk_size);
+    let (mut scanned, mut updated) = (0u64, 0u64);
+    let mut last_id = None;
+    tracing::info!(target: TARGET, "Recomputing derived order fields{}", after.map(|id| format!(" after {}", id)).unwrap_or_default());
+
+    loop {
+        let order = cursor.try_next().await?;
+        if let Some(order) = &order {
+            scanned += 1;
+            last_id = order.get_object_id("_id").ok();
+            if let Some(update) = derived_update(order) {
+                let id = order.get_object_id("_id").expect("stored orders have an ObjectId _id");
+                updates.push(
+                    UpdateOneModel::builder()
+                        .namespace(collection.namespace())
+                        .filter(doc! { "_id": id })
+                        .update(update)
+                        .build(),
+                );
+            }
+        }
+
+        let done = order.is_none();
+        if updates.len() >= chunk_size || (done && !updates.is_empty()) {
+            let result = get_client().bulk_write(std::mem::take(&mut updates)).ordered(false).await?;
+            updated += result.modified_count as u64;
+            tracing::info!(
+                target: TARGET,
+                scanned,
+                updated,
+                last_id = ?last_id.map(|id| id.to_hex()),
+                "Recompute progress"
+            );
+        }
+        if done {
+            break;
+        }
+    }
+
+    tracing::info!(target: TARGET, scanned, updated, "Recompute finished");
+    Ok(())
+}
+
+/// `$set`/`$unset` bringing an order's derived fields in line, or `None` if they already are.
+/// `modified_at` moves too, so delta-syncing clients pick up renormalized tags.
+fn derived_update(order: &Document) -> Option<Document> {
+    let mut set = Document::new();
+    let mut unset = Document::new();
+
+    let order_date_iso = order.get_str("order_date").ok().and_then(normalize_order_date);
+    if order.get_str("order_date_iso").ok() != order_date_iso.as_deref() {
+        match order_date_iso {
+            Some(iso) => set.insert("order_date_iso", iso),
+            None => unset.insert("order_date_iso", ""),
+        };
+    }
+
+    let stored_tags: Vec<String> = order
+        .get_array("tags")
+        .map(|tags| tags.iter().filter_map(|t| t.as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    let tags = normalize_tags(&stored_tags);
+    if tags != stored_tags {
+        if tags.is_empty() {
+            unset.insert("tags", "");
+        } else {
+            set.insert("tags", tags);
+        }
+    }
+
+    if set.is_empty() && unset.is_empty() {
+        return None;
+    }
+    set.insert("modified_at", bson::DateTime::now());
+    let mut update = doc! { "$set": set };
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    Some(update)
+}
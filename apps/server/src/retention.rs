@@ -0,0 +1,268 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::DeleteOneModel,
+};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use crate::auth::Owner;
+use crate::config::get_config;
+use crate::dates::now_rfc3339;
+use crate::db::{get_client, orders_collection};
+use crate::errors::{AppError, AppResult};
+use crate::list_cache;
+use crate::routes::{images::delete_images, orders::record_tombstones};
+use crate::webhooks::{self, OrderEventType};
+
+/// Tracing target for retention runs (`RUST_LOG=order_wizard::retention=info`)
+const TARGET: &str = "order_wizard::retention";
+
+/// How often expired orders are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What happens to an order once it is older than `ORDER_RETENTION_DAYS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionAction {
+    /// Removed with its uploaded image; a tombstone makes its id answer 410
+    Delete,
+    /// Marked deleted (`deletedAt`) so syncing clients drop it too, but kept stored
+    SoftDelete,
+}
+
+impl FromStr for RetentionAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(RetentionAction::Delete),
+            "soft-delete" => Ok(RetentionAction::SoftDelete),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Start the hourly retention sweep when `ORDER_RETENTION_DAYS` is set
+pub fn spawn() {
+    let Some(days) = get_config().order_retention_days else {
+        return;
+    };
+    let action = get_config().order_retention_action;
+    tracing::debug!(target: TARGET, "Orders expire {} days after their last change ({:?})", days, action);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match sweep(days).await {
+                Ok(0) => tracing::debug!(target: TARGET, "No expired orders"),
+                Ok(count) => tracing::info!(target: TARGET, "Expired {} orders", count),
+                Err(e) => tracing::warn!(target: TARGET, "Retention sweep failed: {:?}", e),
+            }
+        }
+    });
+}
+
+/// Orders the server has not written to since the cutoff. Client timestamps such as
+/// `created_at` are free-form text and cannot be compared reliably, so age comes from
+/// `modified_at`; orders stored before that field existed fall back to the insert time
+/// in their `_id`.
+fn expired_filter(days: u32) -> Document {
+    let cutoff = bson::DateTime::from_millis(
+        bson::DateTime::now().timestamp_millis() - i64::from(days) * 86_400_000,
+    );
+    doc! {
+        "deleted_at": null,
+        "$or": [
+            { "modified_at": { "$lt": cutoff } },
+            { "modified_at": null, "_id": { "$lt": first_object_id_at(cutoff) } },
+        ],
+    }
+}
+
+/// The lowest ObjectId generated at `time`, for comparing `_id`s by insert time
+fn first_object_id_at(time: bson::DateTime) -> ObjectId {
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&((time.timestamp_millis() / 1000) as u32).to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+async fn sweep(days: u32) -> AppResult<u64> {
+    let collection = orders_collection();
+    let filter = expired_filter(days);
+
+    if get_config().order_retention_action == RetentionAction::SoftDelete {
+        let result = collection
+            .update_many(
+                filter,
+                doc! { "$set": { "deleted_at": now_rfc3339(), "modified_at": bson::DateTime::now() } },
+            )
+            .await
+            .map_err(AppError::database)?;
+        return Ok(result.modified_count);
+    }
+
+    let chunk_size = get_config().bulk_write_chunk_size;
+    let mut cursor = collection
+        .clone_with_type::<Document>()
+        .find(filter.clone())
+        .projection(doc! { "id": 1, "user_id": 1, "tenant_id": 1 })
+        .await
+        .map_err(AppError::database)?;
+    let mut expired = Vec::with_capacity(chunk_size);
+    let mut deleted = 0;
+    while let Some(order) = cursor.try_next().await.map_err(AppError::database)? {
+        if let (Ok(user_id), Ok(id)) = (order.get_str("user_id"), order.get_str("id")) {
+            let owner = Owner {
+                user_id: user_id.to_string(),
+                tenant_id: order.get_str("tenant_id").ok().map(String::from),
+            };
+            expired.push((owner, id.to_string()));
+        }
+        if expired.len() == chunk_size {
+            deleted += purge(&filter, std::mem::take(&mut expired)).await?;
+        }
+    }
+    deleted += purge(&filter, expired).await?;
+    Ok(deleted)
+}
+
+/// Delete one chunk of scanned orders that are still expired. Orders changed since the
+/// scan no longer match `filter` and are kept; only the ones removed get a tombstone,
+/// lose their image and are reported like `DELETE /orders/{id}`.
+async fn purge(filter: &Document, expired: Vec<(Owner, String)>) -> AppResult<u64> {
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    let namespace = orders_collection().namespace();
+    let models = expired.iter().map(|(owner, id)| {
+        let mut conditions = filter.clone();
+        conditions.insert("id", id);
+        DeleteOneModel::builder().namespace(namespace.clone()).filter(owner.filter(conditions)).build()
+    });
+    let result = get_client()
+        .bulk_write(models)
+        .ordered(false)
+        .verbose_results()
+        .await
+        .map_err(AppError::database)?;
+
+    let mut removed: HashMap<&Owner, Vec<String>> = HashMap::new();
+    for (index, delete) in &result.delete_results {
+        if delete.deleted_count > 0 {
+            let (owner, id) = &expired[*index];
+            removed.entry(owner).or_default().push(id.clone());
+        }
+    }
+    for (owner, ids) in removed {
+        record_tombstones(owner, &ids).await?;
+        delete_images(owner, &ids).await?;
+        list_cache::invalidate(owner).await;
+        for id in &ids {
+            webhooks::order_event(OrderEventType::Deleted, owner, id);
+        }
+    }
+    Ok(result.summary.deleted_count as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{run, unique_user};
+
+    #[test]
+    fn object_ids_compare_by_insert_time() {
+        let time = bson::DateTime::from_millis(1_700_000_000_500);
+        let first = first_object_id_at(time);
+        assert_eq!(first.timestamp().timestamp_millis(), 1_700_000_000_000);
+        assert!(first <= ObjectId::from_bytes([0x65, 0x53, 0xf1, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]));
+        assert!(first > ObjectId::from_bytes([0x65, 0x53, 0xf0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]));
+    }
+
+    #[test]
+    fn age_is_taken_from_server_timestamps_only() {
+        let filter = expired_filter(30);
+        let text = filter.to_string();
+        assert!(!text.contains("created_at"), "client timestamps must not decide expiry: {}", text);
+        let cutoff = filter.get_array("$or").unwrap()[0].as_document().unwrap().get_document("modified_at").unwrap();
+        let cutoff = cutoff.get_datetime("$lt").unwrap().timestamp_millis();
+        let expected = bson::DateTime::now().timestamp_millis() - 30 * 86_400_000;
+        assert!((expected - cutoff).abs() < 60_000);
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn orders_expire_by_last_server_write() {
+        run(async {
+            let user = unique_user();
+            let days_ago = |days: i64| bson::DateTime::from_millis(bson::DateTime::now().timestamp_millis() - days * 86_400_000);
+            let inserted_at = |time| {
+                let mut bytes = first_object_id_at(time).bytes();
+                bytes[4..].copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..8]);
+                ObjectId::from_bytes(bytes)
+            };
+            // Ids are unique per run, so leftovers of earlier runs never clash
+            let id = |name: &str| format!("{}-{}", name, user);
+            let orders = orders_collection().clone_with_type::<Document>();
+            orders
+                .insert_many([
+                    // Client dates in formats that sort wrongly as text must not matter
+                    doc! { "user_id": &user, "id": id("old"), "created_at": "9999-01-01", "modified_at": days_ago(40) },
+                    doc! { "user_id": &user, "id": id("recent"), "created_at": "01/02/2000", "modified_at": days_ago(5) },
+                    doc! { "user_id": &user, "id": id("old-deleted"), "modified_at": days_ago(40), "deleted_at": "2024-01-01T00:00:00Z" },
+                    // Stored before modified_at existed: its _id says when it was inserted
+                    doc! { "_id": inserted_at(days_ago(40)), "user_id": &user, "id": id("legacy") },
+                    doc! { "user_id": &user, "id": id("legacy-recent") },
+                ])
+                .await
+                .unwrap();
+
+            let mut filter = expired_filter(30);
+            filter.insert("user_id", &user);
+            let mut expired: Vec<String> = orders
+                .distinct("id", filter)
+                .await
+                .unwrap()
+                .into_iter()
+                .filter_map(|id| id.as_str().map(String::from))
+                .collect();
+            expired.sort();
+            assert_eq!(expired, [id("legacy"), id("old")]);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn orders_changed_after_the_scan_are_kept() {
+        run(async {
+            let owner = Owner { user_id: unique_user(), tenant_id: None };
+            let days_ago = bson::DateTime::from_millis(bson::DateTime::now().timestamp_millis() - 40 * 86_400_000);
+            let (stale, edited) = (uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string());
+            let orders = orders_collection().clone_with_type::<Document>();
+            orders
+                .insert_many([
+                    doc! { "user_id": &owner.user_id, "tenant_id": null, "id": &stale, "modified_at": days_ago },
+                    doc! { "user_id": &owner.user_id, "tenant_id": null, "id": &edited, "modified_at": days_ago },
+                ])
+                .await
+                .unwrap();
+
+            let filter = expired_filter(30);
+            let scanned = vec![(owner.clone(), stale.clone()), (owner.clone(), edited.clone())];
+            // The user edits one order between the scan and the delete
+            orders
+                .update_one(owner.filter(doc! { "id": &edited }), doc! { "$set": { "modified_at": bson::DateTime::now() } })
+                .await
+                .unwrap();
+
+            assert_eq!(purge(&filter, scanned).await.unwrap(), 1);
+            let remaining = orders.count_documents(owner.filter(doc! {})).await.unwrap();
+            assert_eq!(remaining, 1);
+            assert!(orders.find_one(owner.filter(doc! { "id": &edited })).await.unwrap().is_some());
+            let tombstones = crate::db::tombstones_collection();
+            let tombstoned: Vec<Document> = tombstones.find(owner.filter(doc! {})).await.unwrap().try_collect().await.unwrap();
+            assert_eq!(tombstoned.len(), 1);
+            assert_eq!(tombstoned[0].get_str("id").unwrap(), stale);
+        });
+    }
+}
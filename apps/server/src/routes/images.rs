@@ -0,0 +1,430 @@
+use axum::{
+    extract::{DefaultBodyLimit, FromRequest, Multipart, Path, Request},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::{AsyncReadExt, AsyncWriteExt, TryStreamExt};
+use mongodb::{
+    bson::{self, doc, Document},
+    options::ReturnDocument,
+};
+use reqwest::redirect;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::{AuthError, AuthUser, Owner};
+use crate::config::get_config;
+use crate::db::{images_bucket, orders_collection, retry_read};
+use crate::errors::{ApiError, AppError, AppResult};
+use crate::extract::AppJson;
+use crate::models::{ImageUpload, Order, UpdateImageRequest};
+use crate::webhooks::{self, OrderEventType};
+
+const ALLOWED_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/gif"];
+
+/// How long `GET /orders/{id}/pdf` waits for a remote product image
+const REMOTE_IMAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Slack on top of the image limit for multipart boundaries and part headers
+const MULTIPART_OVERHEAD_BYTES: usize = 16 * 1024;
+
+/// Tracing targets per route (`RUST_LOG=order_wizard::images=debug`)
+mod targets {
+    pub const GET: &str = "order_wizard::images::get";
+    pub const PUT: &str = "order_wizard::images::put";
+}
+
+pub fn router() -> OpenApiRouter {
+    let body_limit = get_config().max_image_bytes + MULTIPART_OVERHEAD_BYTES;
+
+    OpenApiRouter::new()
+        .routes(routes!(get_order_image, put_order_image))
+        .layer(DefaultBodyLimit::max(body_limit))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/image",
+    tag = "Orders",
+    summary = "Get an uploaded product image",
+    description = "Returns the image uploaded for an order via PUT /orders/{id}/image",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Image bytes", body = [u8], content_type = "image/*"),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn get_order_image(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<Response> {
+    tracing::info!(target: targets::GET, "GET /orders/{}/image - user: {}", id, claims.sub);
+
+    let owner = claims.owner();
+    let bucket = images_bucket();
+    let file = retry_read("GET /orders/{id}/image", || bucket.find_one(image_filter(&id, &owner)))
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Image"))?;
+
+    let content_type = file
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get_str("content_type").ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let mut bytes = Vec::with_capacity(file.length as usize);
+    bucket
+        .open_download_stream(file.id)
+        .await
+        .map_err(AppError::database)?
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+    Ok(([(header::CONTENT_TYPE, content_type)], bytes).into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/orders/{id}/image",
+    tag = "Orders",
+    summary = "Replace an order's product image",
+    description = "Sets the product image from a URL (JSON body) or an uploaded file (multipart `image` field). \
+        Uploaded files are stored server-side and `productImage` then points at GET /orders/{id}/image.",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    request_body(content(
+        (UpdateImageRequest = "application/json"),
+        (ImageUpload = "multipart/form-data")
+    )),
+    responses(
+        (status = 200, description = "Image updated", body = Order),
+        (status = 400, description = "Invalid URL or upload", body = ApiError),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 413, description = "Image exceeds the size limit", body = ApiError),
+        (status = 415, description = "Unsupported body or image type", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn put_order_image(
+    AuthUser(claims): AuthUser,
+    Path(id): Path<String>,
+    request: Request,
+) -> AppResult<Json<Order>> {
+    tracing::info!(target: targets::PUT, "PUT /orders/{}/image - user: {}", id, claims.sub);
+
+    let owner = claims.owner();
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let product_image = if content_type.starts_with("application/json") {
+        let AppJson(payload) = AppJson::<UpdateImageRequest>::from_request(request, &()).await?;
+        if !payload.url.starts_with("https://") && !payload.url.starts_with("http://") {
+            return Err(AppError::bad_request("Image URL must use http or https"));
+        }
+        ensure_order_exists(&id, &owner).await?;
+        delete_images(&owner, std::slice::from_ref(&id)).await?;
+        payload.url
+    } else if content_type.starts_with("multipart/form-data") {
+        let multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| AppError::bad_request(e.body_text()))?;
+        ensure_order_exists(&id, &owner).await?;
+        let (image_type, bytes) = read_image_field(multipart).await?;
+        delete_images(&owner, std::slice::from_ref(&id)).await?;
+        store_image(&id, &owner, &image_type, &bytes).await?;
+        format!("/orders/{}/image", id)
+    } else {
+        return Err(AppError::unsupported_media_type(
+            "Expected application/json or multipart/form-data",
+        ));
+    };
+
+    let entity = orders_collection()
+        .find_one_and_update(
+            owner.filter(doc! { "id": &id }),
+            doc! { "$set": { "product_image": &product_image, "modified_at": bson::DateTime::now() } },
+        )
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+
+    tracing::info!(target: targets::PUT, "PUT /orders/{}/image - updated", id);
+    let order = Order::from(entity);
+    webhooks::order_changed(OrderEventType::Updated, &owner, order.clone());
+    Ok(Json(order))
+}
+
+/// Remove any uploaded images belonging to the given orders
+pub async fn delete_images(owner: &Owner, order_ids: &[String]) -> AppResult<()> {
+    let bucket = images_bucket();
+    let files: Vec<_> = bucket
+        .find(owner.metadata_filter(doc! { "filename": { "$in": order_ids } }))
+        .await
+        .map_err(AppError::database)?
+        .try_collect()
+        .await
+        .map_err(AppError::database)?;
+
+    for file in files {
+        bucket.delete(file.id).await.map_err(AppError::database)?;
+    }
+    Ok(())
+}
+
+/// Move uploaded images to an order's new id, so its image URL keeps resolving
+pub async fn rename_images(owner: &Owner, from: &str, to: &str) -> AppResult<()> {
+    let bucket = images_bucket();
+    let files: Vec<_> = bucket
+        .find(owner.metadata_filter(doc! { "filename": from }))
+        .await
+        .map_err(AppError::database)?
+        .try_collect()
+        .await
+        .map_err(AppError::database)?;
+
+    for file in files {
+        bucket.rename(file.id, to).await.map_err(AppError::database)?;
+    }
+    Ok(())
+}
+
+/// Bytes of an order's product image for rendering, from the upload store or its
+/// https URL on a `REMOTE_IMAGE_HOSTS` host. Anything missing, unreachable, not allowed
+/// or over `max_image_bytes` yields `None`.
+pub async fn load_product_image(order: &Order, owner: &Owner) -> Option<Vec<u8>> {
+    let max_bytes = get_config().max_image_bytes;
+    let result = if order.product_image == format!("/orders/{}/image", order.id) {
+        read_stored_image(&order.id, owner, max_bytes).await
+    } else if order.product_image.starts_with("https://") || order.product_image.starts_with("http://") {
+        fetch_remote_image(&order.product_image, max_bytes).await
+    } else {
+        return None;
+    };
+
+    result
+        .inspect_err(|e| tracing::warn!(target: targets::GET, "Product image for order {} unavailable: {}", order.id, e))
+        .ok()
+}
+
+async fn read_stored_image(order_id: &str, owner: &Owner, max_bytes: usize) -> Result<Vec<u8>, String> {
+    let bucket = images_bucket();
+    let file = bucket
+        .find_one(image_filter(order_id, owner))
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("no uploaded image")?;
+    if file.length > max_bytes as u64 {
+        return Err(format!("{} bytes exceeds the limit", file.length));
+    }
+
+    let mut bytes = Vec::with_capacity(file.length as usize);
+    bucket
+        .open_download_stream(file.id)
+        .await
+        .map_err(|e| e.to_string())?
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Download a product image. The URL comes from the client, so only allowlisted hosts
+/// are contacted, only at the public addresses they resolve to (the checked addresses
+/// are pinned, so a second lookup cannot swap them) and without following redirects.
+async fn fetch_remote_image(url: &str, max_bytes: usize) -> Result<Vec<u8>, String> {
+    let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    let host = allowed_image_host(&url, &get_config().remote_image_hosts).ok_or("host is not in REMOTE_IMAGE_HOSTS")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| e.to_string())?
+        .collect();
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+        return Err(format!("{} resolves to a non-public address", host));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(REMOTE_IMAGE_TIMEOUT)
+        .redirect(redirect::Policy::none())
+        .resolve_to_addrs(&host, &addrs)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("answered {}", response.status()));
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(format!("exceeds {} bytes", max_bytes));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// The URL's host when it is https and listed in `hosts` (lowercase)
+fn allowed_image_host(url: &reqwest::Url, hosts: &[String]) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    (url.scheme() == "https" && hosts.contains(&host)).then_some(host)
+}
+
+/// Whether an address is reachable on the public internet, i.e. not loopback, private,
+/// link-local (cloud metadata), shared, documentation, multicast or unspecified
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_ip(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+fn image_filter(order_id: &str, owner: &Owner) -> Document {
+    owner.metadata_filter(doc! { "filename": order_id })
+}
+
+async fn ensure_order_exists(id: &str, owner: &Owner) -> AppResult<()> {
+    let collection = orders_collection();
+    retry_read("PUT /orders/{id}/image", || collection.find_one(owner.filter(doc! { "id": id })))
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+    Ok(())
+}
+
+/// Read the `image` multipart field, enforcing allowed types and the size limit
+async fn read_image_field(mut multipart: Multipart) -> AppResult<(String, Vec<u8>)> {
+    let max_bytes = get_config().max_image_bytes;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() != Some("image") {
+            continue;
+        }
+
+        let image_type = field.content_type().unwrap_or_default().to_string();
+        if !ALLOWED_IMAGE_TYPES.contains(&image_type.as_str()) {
+            return Err(AppError::unsupported_media_type(format!(
+                "Image type must be one of: {}",
+                ALLOWED_IMAGE_TYPES.join(", ")
+            )));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(AppError::payload_too_large(format!(
+                    "Image exceeds maximum of {} bytes",
+                    max_bytes
+                )));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        return Ok((image_type, bytes));
+    }
+
+    Err(AppError::bad_request("Missing `image` field"))
+}
+
+fn multipart_error(err: axum::extract::multipart::MultipartError) -> AppError {
+    if err.status() == axum::http::StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::payload_too_large(err.body_text())
+    } else {
+        AppError::bad_request(err.body_text())
+    }
+}
+
+async fn store_image(order_id: &str, owner: &Owner, image_type: &str, bytes: &[u8]) -> AppResult<()> {
+    let mut metadata = owner.metadata();
+    metadata.insert("content_type", image_type);
+    let mut upload = images_bucket()
+        .open_upload_stream(order_id)
+        .metadata(metadata)
+        .await
+        .map_err(AppError::database)?;
+
+    upload
+        .write_all(bytes)
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    upload
+        .close()
+        .await
+        .map_err(|e| AppError::Database(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts() -> Vec<String> {
+        vec!["m.media-amazon.com".to_string()]
+    }
+
+    fn host_of(url: &str) -> Option<String> {
+        allowed_image_host(&reqwest::Url::parse(url).unwrap(), &hosts())
+    }
+
+    #[test]
+    fn only_listed_https_hosts_are_fetched() {
+        assert_eq!(host_of("https://m.media-amazon.com/images/I/x.jpg").as_deref(), Some("m.media-amazon.com"));
+        assert_eq!(host_of("https://M.Media-Amazon.com/x.jpg").as_deref(), Some("m.media-amazon.com"));
+        assert_eq!(host_of("http://m.media-amazon.com/x.jpg"), None);
+        assert_eq!(host_of("https://169.254.169.254/latest/meta-data/"), None);
+        assert_eq!(host_of("https://localhost/x.jpg"), None);
+        assert_eq!(host_of("https://m.media-amazon.com.evil.example/x.jpg"), None);
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for ip in ["52.84.1.1", "8.8.8.8", "2600:9000::1", "::ffff:8.8.8.8"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use axum::{
+    extract::rejection::FormRejection,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use sha2::{Digest, Sha256};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::{AuthError, JwksVerifier};
+use crate::config::get_config;
+use crate::errors::{ApiError, AppError};
+use crate::models::{IntrospectionRequest, IntrospectionResponse};
+
+/// Tracing target for introspection calls (`RUST_LOG=order_wizard::introspect=debug`)
+const TARGET: &str = "order_wizard::introspect";
+
+pub fn router() -> OpenApiRouter {
+    OpenApiRouter::new().routes(routes!(introspect_token))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/introspect",
+    tag = "Auth",
+    summary = "Introspect an access token",
+    description = "Server-to-server check of a user's access token, modelled on RFC 7662. Callers authenticate \
+        with the shared `INTROSPECTION_SECRET` as a bearer token. Returns `{\"active\": false}` for any token \
+        that is expired, malformed or not issued for this app. Not available unless the secret is configured.",
+    request_body(content = IntrospectionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Token state", body = IntrospectionResponse),
+        (status = 400, description = "Missing or malformed form body", body = ApiError),
+        (status = 401, description = "Caller did not present the introspection secret", body = AuthError),
+        (status = 404, description = "Introspection is not configured", body = ApiError)
+    ),
+    security(("introspection_secret" = []))
+)]
+async fn introspect_token(
+    headers: HeaderMap,
+    form: Result<Form<IntrospectionRequest>, FormRejection>,
+) -> Response {
+    let Some(secret) = &get_config().introspection_secret else {
+        return AppError::not_found("Introspection endpoint").into_response();
+    };
+
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    if !presented.is_some_and(|p| secrets_match(p, secret)) {
+        tracing::warn!(target: TARGET, "POST /auth/introspect - rejected caller without the shared secret");
+        return AuthError::invalid_client("Introspection requires the shared secret").into_response();
+    }
+
+    let Ok(Form(request)) = form else {
+        return AppError::bad_request("Expected a form body with a `token` field").into_response();
+    };
+
+    let response = match JwksVerifier::verify(&request.token).await {
+        Some(claims) => IntrospectionResponse::active(claims),
+        None => IntrospectionResponse::inactive(),
+    };
+    tracing::info!(target: TARGET, "POST /auth/introspect - active: {}", response.active);
+    Json(response).into_response()
+}
+
+/// Compare digests rather than the secrets, so timing reveals nothing about the secret
+fn secrets_match(presented: &str, secret: &str) -> bool {
+    let (a, b) = (Sha256::digest(presented), Sha256::digest(secret));
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1 +1,47 @@
+pub mod images;
+pub mod introspect;
 pub mod orders;
+pub mod share;
+pub mod telemetry;
+
+use axum::{
+    extract::Request,
+    http::{uri::PathAndQuery, Uri},
+    middleware, Router,
+};
+
+/// Prefixes whose handlers want the trailing slash: Swagger UI serves its index at
+/// `/swagger-ui/` and redirects `/swagger-ui` there
+const KEEP_TRAILING_SLASH: &[&str] = &["/swagger-ui"];
+
+/// Tolerate trailing slashes on every route by rewriting `/orders/` to `/orders` before
+/// routing. A rewrite rather than a redirect, so clients keep their method, body and
+/// `Authorization` header without an extra round trip. It wraps the finished router
+/// because axum matches routes before `Router::layer` middleware runs.
+pub fn tolerate_trailing_slash(app: Router) -> Router {
+    Router::new()
+        .fallback_service(app)
+        .layer(middleware::map_request(trim_trailing_slash))
+}
+
+async fn trim_trailing_slash(mut request: Request) -> Request {
+    let path = request.uri().path();
+    if path == "/" || !path.ends_with('/') || KEEP_TRAILING_SLASH.iter().any(|p| path.starts_with(p)) {
+        return request;
+    }
+
+    let trimmed = match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed.to_string(),
+    };
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *request.uri_mut() = uri;
+    }
+    request
+}
@@ -1,20 +1,77 @@
-use axum::{extract::Path, http::StatusCode, Json};
-use futures::TryStreamExt;
-use mongodb::bson::doc;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, RawQuery},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::Query as MultiQuery;
+use futures::{stream, StreamExt, TryStreamExt};
+use mongodb::{
+    bson::{self, doc, Document},
+    error::{ErrorKind, PartialBulkWriteResult},
+    options::{ReturnDocument, SelectionCriteria, UpdateOneModel},
+    results::{SummaryBulkWriteResult, UpdateResult},
+    Cursor,
+};
+use std::collections::HashMap;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
-use crate::auth::{AuthError, AuthUser};
-use crate::db::{get_client, orders_collection};
-use crate::errors::{AppError, AppResult};
-use crate::models::{BatchDeleteRequest, BatchDeleteResponse, BatchUpsertRequest, BatchUpsertResponse, CreateOrderRequest, Order, OrderStatus, UpdateOrderRequest};
+use crate::auth::{AuthError, AuthUser, Owner};
+use crate::config::get_config;
+use crate::dates::{normalize_order_date, now_rfc3339, parse_iso_date};
+use crate::db::{self, get_client, orders_collection, retry_read, tombstones_collection, IMAGES_BUCKET};
+use crate::errors::{ApiError, AppError, AppResult};
+use crate::extract::{encode_cursor, page_limit, AppJson, Page, Pagination, Placeholders, Shape};
+use crate::models::{normalize_tags, BatchDeleteRequest, BatchDeleteResponse, BatchGetRequest, BatchItemError, BatchMode, BatchUpsertQuery, BatchUpsertRequest, BatchUpsertResponse, CreateOrderRequest, GroupBy, GroupOrdersQuery, ImportAction, ImportPreview, ImportPreviewItem, ListOrdersQuery, Order, OrderBody, OrderEntity, OrderGroup, OrderStatus, PageParams, PlaceholderParams, RekeyOrderRequest, ShapeParams, StatusCounts, StorageUsage, UpdateOrderRequest, UpsertOrderRequest};
+use crate::list_cache;
+use crate::normalize::Normalize;
+use crate::pdf;
+use crate::routes::images::{delete_images, load_product_image, rename_images};
+use crate::status_format::{self, StatusFormatParams};
+use crate::validation::{check_editable, check_order_number, Validate, ValidationErrors};
+use crate::webhooks::{self, OrderEventType};
+
+/// Tracing targets per route, so one endpoint can be made verbose on its own
+/// (`RUST_LOG=order_wizard::orders::update=debug`) or all of them (`order_wizard::orders=debug`)
+mod targets {
+    pub const LIST: &str = "order_wizard::orders::list";
+    pub const TAGS: &str = "order_wizard::orders::tags";
+    pub const STATUS_COUNTS: &str = "order_wizard::orders::status_counts";
+    pub const GROUPED: &str = "order_wizard::orders::grouped";
+    pub const USAGE: &str = "order_wizard::orders::usage";
+    pub const CREATE: &str = "order_wizard::orders::create";
+    pub const UPSERT: &str = "order_wizard::orders::upsert";
+    pub const BATCH_UPSERT: &str = "order_wizard::orders::batch_upsert";
+    pub const IMPORT_PREVIEW: &str = "order_wizard::orders::import_preview";
+    pub const BATCH_DELETE: &str = "order_wizard::orders::batch_delete";
+    pub const BATCH_GET: &str = "order_wizard::orders::batch_get";
+    pub const GET: &str = "order_wizard::orders::get";
+    pub const PDF: &str = "order_wizard::orders::pdf";
+    pub const UPDATE: &str = "order_wizard::orders::update";
+    pub const DELETE: &str = "order_wizard::orders::delete";
+    pub const ARCHIVE: &str = "order_wizard::orders::archive";
+    pub const REKEY: &str = "order_wizard::orders::rekey";
+}
 
 pub fn router() -> OpenApiRouter {
     OpenApiRouter::new()
         .routes(routes!(list_orders))
+        .routes(routes!(list_tags))
+        .routes(routes!(status_counts))
+        .routes(routes!(grouped_orders))
+        .routes(routes!(storage_usage))
         .routes(routes!(create_order))
         .routes(routes!(batch_upsert_orders))
+        .routes(routes!(preview_import))
         .routes(routes!(batch_delete_orders))
+        .routes(routes!(batch_get_orders))
+        .routes(routes!(upsert_order_by_number))
+        .routes(routes!(archive_order))
+        .routes(routes!(unarchive_order))
+        .routes(routes!(rekey_order))
         .routes(routes!(get_order))
+        .routes(routes!(get_order_pdf))
         .routes(routes!(update_order))
         .routes(routes!(delete_order))
 }
@@ -24,28 +81,524 @@ pub fn router() -> OpenApiRouter {
     path = "/orders",
     tag = "Orders",
     summary = "List all orders",
-    description = "Returns all orders for the authenticated user",
+    description = "Returns all orders for the authenticated user. `from`/`to` filter on the normalized order date; \
+        orders whose date could not be parsed are excluded from ranged queries. Each `tag` narrows the list to \
+        orders carrying that tag. `updatedSince` returns only orders written after that time, soft-deleted ones \
+        included; send back the previous response's `X-Server-Time` for incremental sync. It cannot be combined \
+        with `from`, `to`, `tag` or `includeArchived=false`, which would hide changed orders. Archived orders are \
+        left out unless `includeArchived=true` (delta-syncing clients should send it). Without `limit`/`cursor` every matching order is returned; with them the \
+        list is paged in a stable order and `X-Next-Cursor` carries the cursor for the next page.",
+    params(ListOrdersQuery, PageParams, PlaceholderParams, StatusFormatParams, ShapeParams),
     responses(
-        (status = 200, description = "List of orders", body = Vec<Order>),
+        (status = 200, description = "List of orders", body = Vec<OrderBody>,
+            headers(
+                ("X-Next-Cursor" = String, description = "Cursor for the next page, when there is one"),
+                ("X-Server-Time" = String, description = "Server time (RFC 3339) before the query ran; the next `updatedSince`"),
+                ("X-First-Time" = bool, description = "On an empty list when `LIST_FIRST_TIME_HINT` is on: `true` if the user has never stored an order")
+            )),
+        (status = 400, description = "Malformed date range or updatedSince, or conflicting parameters", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
-async fn list_orders(AuthUser(claims): AuthUser) -> AppResult<Json<Vec<Order>>> {
-    tracing::info!("GET /orders - user: {}", claims.sub);
+async fn list_orders(
+    AuthUser(claims): AuthUser,
+    RawQuery(raw_query): RawQuery,
+    MultiQuery(query): MultiQuery<ListOrdersQuery>,
+    Pagination(page): Pagination,
+    placeholders: Placeholders,
+    shape: Shape,
+) -> AppResult<Response> {
+    tracing::info!(target: targets::LIST, "GET /orders - user: {}", claims.sub);
+
+    // Streamed lists are never buffered, so they bypass the cache
+    if page.is_none() && get_config().stream_order_list {
+        return load_orders(&claims.owner(), query, page, placeholders, shape).await;
+    }
+    let owner = claims.owner();
+    let raw_query = raw_query.unwrap_or_default();
+    list_cache::cached_list(&owner, &raw_query, load_orders(&owner, query, page, placeholders, shape)).await
+}
 
-    let entities: Vec<_> = orders_collection()
-        .find(doc! { "user_id": &claims.sub })
+/// `GET /orders` itself, run on list cache misses
+async fn load_orders(
+    owner: &Owner,
+    query: ListOrdersQuery,
+    page: Option<Page>,
+    placeholders: Placeholders,
+    shape: Shape,
+) -> AppResult<Response> {
+    // Taken before querying, so a client that sends it back as `updatedSince` misses nothing
+    let server_time = now_rfc3339();
+
+    let collection = orders_collection();
+    let mut filter = owner.filter(doc! {});
+    let mut errors = ValidationErrors::default();
+    query.validate_into("", &mut errors);
+    if let Some(range) = order_date_range(&query, &mut errors) {
+        filter.insert("order_date_iso", range);
+    }
+    if let Some(since) = updated_since(&query, &mut errors) {
+        filter.insert("modified_at", doc! { "$gt": since });
+    }
+    errors.into_result()?;
+    if query.include_archived != Some(true) {
+        filter.insert("archived", doc! { "$ne": true });
+    }
+    let tags = normalize_tags(&query.tag);
+    if !tags.is_empty() {
+        filter.insert("tags", doc! { "$all": tags });
+    }
+    let max_time = get_config().db_max_time;
+    // Delta sync stays on the primary: a lagging secondary could miss writes made just
+    // before the `X-Server-Time` a client sends back as `updatedSince`
+    let criteria = if query.updated_since.is_some() { db::primary() } else { db::read_only() };
+
+    // Emptiness is unknown for streamed lists, so they never get `X-First-Time`
+    let (mut response, empty) = if let Some(page) = page {
+        list_orders_page(owner, filter, page, criteria, placeholders, shape).await?
+    } else if get_config().stream_order_list {
+        let cursor = retry_read("GET /orders", || {
+            collection
+                .find(filter.clone())
+                .max_time(max_time)
+                .selection_criteria(criteria.clone())
+        })
+            .await
+            .map_err(AppError::database)?;
+        tracing::info!(target: targets::LIST, "GET /orders - streaming response");
+        (stream_orders(cursor, placeholders, shape), false)
+    } else {
+        let entities: Vec<_> = retry_read("GET /orders", || async {
+            collection
+                .find(filter.clone())
+                .max_time(max_time)
+                .selection_criteria(criteria.clone())
+                .await?
+                .try_collect()
+                .await
+        })
         .await
-        .map_err(AppError::database)?
-        .try_collect()
+        .map_err(AppError::database)?;
+
+        let orders: Vec<OrderBody> = entities
+            .into_iter()
+            .map(|e| shape.apply(placeholders.apply(Order::from(e))))
+            .collect();
+
+        tracing::info!(target: targets::LIST, "GET /orders - returning {} orders", orders.len());
+        let empty = orders.is_empty();
+        (Json(orders).into_response(), empty)
+    };
+
+    response.headers_mut().insert(
+        "x-server-time",
+        HeaderValue::from_str(&server_time).expect("RFC 3339 timestamp is a valid header value"),
+    );
+    if empty && get_config().first_time_hint {
+        let first_time = is_first_time(owner).await?;
+        response
+            .headers_mut()
+            .insert("x-first-time", HeaderValue::from_static(if first_time { "true" } else { "false" }));
+    }
+    Ok(response)
+}
+
+/// Whether the user has never stored an order: no order documents (soft-deleted ones
+/// included) and no tombstones left by purged ones
+async fn is_first_time(owner: &Owner) -> AppResult<bool> {
+    let filter = owner.filter(doc! {});
+    let orders = orders_collection();
+    let stored = retry_read("GET /orders first-time check", || {
+        orders
+            .count_documents(filter.clone())
+            .limit(1)
+            .max_time(get_config().db_max_time)
+    })
         .await
         .map_err(AppError::database)?;
+    if stored > 0 {
+        return Ok(false);
+    }
 
-    let orders: Vec<Order> = entities.into_iter().map(Order::from).collect();
+    let tombstones = tombstones_collection();
+    let purged = retry_read("GET /orders first-time check", || {
+        tombstones
+            .count_documents(filter.clone())
+            .limit(1)
+            .max_time(get_config().db_max_time)
+    })
+        .await
+        .map_err(AppError::database)?;
+    Ok(purged == 0)
+}
 
-    tracing::info!("GET /orders - returning {} orders", orders.len());
-    Ok(Json(orders))
+#[utoipa::path(
+    get,
+    path = "/orders/tags",
+    tag = "Orders",
+    summary = "List tags in use",
+    description = "Returns the distinct tags across the authenticated user's orders, sorted",
+    responses(
+        (status = 200, description = "Tags in use", body = Vec<String>),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn list_tags(AuthUser(claims): AuthUser) -> AppResult<Json<Vec<String>>> {
+    tracing::info!(target: targets::TAGS, "GET /orders/tags - user: {}", claims.sub);
+
+    let collection = orders_collection();
+    let values = retry_read("GET /orders/tags", || {
+        collection
+            .distinct("tags", claims.owner().filter(doc! {}))
+            .max_time(get_config().db_max_time)
+            .selection_criteria(db::read_only())
+    })
+    .await
+    .map_err(AppError::database)?;
+
+    let mut tags: Vec<String> = values
+        .into_iter()
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect();
+    tags.sort();
+
+    tracing::info!(target: targets::TAGS, "GET /orders/tags - returning {} tags", tags.len());
+    Ok(Json(tags))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/status-counts",
+    tag = "Orders",
+    summary = "Count orders by status",
+    description = "Returns how many non-deleted, non-archived orders the user has in each status, for tab badges. \
+        Statuses without orders are reported as 0.",
+    responses(
+        (status = 200, description = "Order counts per status", body = StatusCounts),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn status_counts(AuthUser(claims): AuthUser) -> AppResult<Json<StatusCounts>> {
+    tracing::info!(target: targets::STATUS_COUNTS, "GET /orders/status-counts - user: {}", claims.sub);
+
+    let pipeline = [
+        doc! { "$match": claims.owner().filter(doc! { "deleted_at": null, "archived": { "$ne": true } }) },
+        doc! { "$group": { "_id": "$status", "count": { "$sum": 1 } } },
+    ];
+    let collection = orders_collection();
+    let groups: Vec<Document> = retry_read("GET /orders/status-counts", || async {
+        collection
+            .aggregate(pipeline.clone())
+            .max_time(get_config().db_max_time)
+            .selection_criteria(db::read_only())
+            .await?
+            .try_collect()
+            .await
+    })
+    .await
+    .map_err(AppError::database)?;
+
+    let mut counts = StatusCounts::default();
+    for group in groups {
+        let status = group
+            .get("_id")
+            .cloned()
+            .and_then(|id| bson::from_bson::<OrderStatus>(id).ok());
+        let count = group.get_i32("count").map(i64::from).or_else(|_| group.get_i64("count"));
+        match (status, count) {
+            (Some(status), Ok(count)) => counts.add(&status, count as u64),
+            _ => tracing::warn!(target: targets::STATUS_COUNTS, "Skipping unexpected status group: {}", group),
+        }
+    }
+
+    Ok(Json(counts))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/grouped",
+    tag = "Orders",
+    summary = "List orders grouped by a field",
+    description = "Groups the user's non-deleted, non-archived orders by status or by order month. Each group \
+        carries its full `count` but at most `limit` orders, newest first. Status groups are sorted by name, \
+        month groups newest first with undated orders (`key: null`) last.",
+    params(GroupOrdersQuery, PlaceholderParams, StatusFormatParams, ShapeParams),
+    responses(
+        (status = 200, description = "Order groups", body = Vec<OrderGroup>),
+        (status = 400, description = "Unknown `by` or invalid limit", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn grouped_orders(
+    AuthUser(claims): AuthUser,
+    Query(query): Query<GroupOrdersQuery>,
+    placeholders: Placeholders,
+    shape: Shape,
+) -> AppResult<Json<Vec<OrderGroup>>> {
+    tracing::info!(target: targets::GROUPED, "GET /orders/grouped - user: {}, by: {:?}", claims.sub, query.by);
+
+    let config = get_config();
+    let mut errors = ValidationErrors::default();
+    let limit = page_limit(query.limit.as_deref(), &mut errors);
+    errors.into_result()?;
+
+    let (key, group_order) = match query.by {
+        GroupBy::Status => (bson::Bson::from("$status"), 1),
+        GroupBy::Month => (
+            doc! { "$cond": [
+                { "$eq": [{ "$type": "$order_date_iso" }, "string"] },
+                { "$substrBytes": ["$order_date_iso", 0, 7] },
+                null,
+            ] }
+            .into(),
+            -1,
+        ),
+    };
+    let pipeline = [
+        doc! { "$match": claims.owner().filter(doc! { "deleted_at": null, "archived": { "$ne": true } }) },
+        doc! { "$sort": { "order_date_iso": -1, "id": 1 } },
+        doc! { "$group": {
+            "_id": key,
+            "count": { "$sum": 1 },
+            "orders": { "$firstN": { "input": "$$ROOT", "n": limit as i64 } },
+        } },
+        doc! { "$sort": { "_id": group_order } },
+    ];
+    let collection = orders_collection();
+    let groups: Vec<Document> = retry_read("GET /orders/grouped", || async {
+        collection
+            .aggregate(pipeline.clone())
+            .max_time(config.db_max_time)
+            .selection_criteria(db::read_only())
+            .await?
+            .try_collect()
+            .await
+    })
+    .await
+    .map_err(AppError::database)?;
+
+    let groups: Vec<OrderGroup> = groups
+        .into_iter()
+        .filter_map(|group| match bson::from_document::<RawGroup>(group) {
+            Ok(raw) => Some(OrderGroup {
+                key: raw.id,
+                count: raw.count as u64,
+                orders: raw.orders.into_iter().map(|e| shape.apply(placeholders.apply(Order::from(e)))).collect(),
+            }),
+            Err(e) => {
+                tracing::warn!(target: targets::GROUPED, "Skipping unexpected order group: {}", e);
+                None
+            }
+        })
+        .collect();
+
+    tracing::info!(target: targets::GROUPED, "GET /orders/grouped - returning {} groups", groups.len());
+    Ok(Json(groups))
+}
+
+/// One `$group` result of [`grouped_orders`]
+#[derive(serde::Deserialize)]
+struct RawGroup {
+    #[serde(rename = "_id")]
+    id: Option<String>,
+    count: i64,
+    orders: Vec<OrderEntity>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/usage",
+    tag = "Orders",
+    summary = "Storage usage",
+    description = "Returns how many orders and product images the user has stored and roughly how many bytes they take. \
+        Archived and soft-deleted orders count, since they are still stored.",
+    responses(
+        (status = 200, description = "Storage used by the user", body = StorageUsage),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn storage_usage(AuthUser(claims): AuthUser) -> AppResult<Json<StorageUsage>> {
+    tracing::info!(target: targets::USAGE, "GET /orders/usage - user: {}", claims.sub);
+
+    // Orders and image files are projected to the same shape so one $group sums both
+    let owner = claims.owner();
+    let pipeline = [
+        doc! { "$match": owner.filter(doc! {}) },
+        doc! { "$project": {
+            "_id": 0,
+            "orders": { "$literal": 1 },
+            "order_bytes": { "$bsonSize": "$$ROOT" },
+            "images": { "$literal": 0 },
+            "image_bytes": { "$literal": 0 },
+        } },
+        doc! { "$unionWith": {
+            "coll": format!("{}.files", IMAGES_BUCKET),
+            "pipeline": [
+                { "$match": owner.metadata_filter(doc! {}) },
+                { "$project": {
+                    "_id": 0,
+                    "orders": { "$literal": 0 },
+                    "order_bytes": { "$literal": 0 },
+                    "images": { "$literal": 1 },
+                    "image_bytes": "$length",
+                } },
+            ],
+        } },
+        doc! { "$group": {
+            "_id": null,
+            "orders": { "$sum": "$orders" },
+            "order_bytes": { "$sum": "$order_bytes" },
+            "images": { "$sum": "$images" },
+            "image_bytes": { "$sum": "$image_bytes" },
+        } },
+    ];
+    let collection = orders_collection();
+    let totals: Option<Document> = retry_read("GET /orders/usage", || async {
+        collection
+            .aggregate(pipeline.clone())
+            .max_time(get_config().db_max_time)
+            .selection_criteria(db::read_only())
+            .await?
+            .try_next()
+            .await
+    })
+    .await
+    .map_err(AppError::database)?;
+
+    // No orders and no images leaves nothing to group: report zeros
+    let Some(totals) = totals else {
+        return Ok(Json(StorageUsage::default()));
+    };
+    let total = |field: &str| match totals.get(field) {
+        Some(bson::Bson::Int32(n)) => *n as u64,
+        Some(bson::Bson::Int64(n)) => *n as u64,
+        Some(bson::Bson::Double(n)) => *n as u64,
+        _ => 0,
+    };
+
+    Ok(Json(StorageUsage {
+        order_count: total("orders"),
+        order_bytes: total("order_bytes"),
+        image_count: total("images"),
+        image_bytes: total("image_bytes"),
+    }))
+}
+
+/// One page of orders ordered by `id`; the cursor holds the last `id` of the previous page.
+/// Also returns whether the list is empty, i.e. this is a first page with no orders.
+async fn list_orders_page(
+    owner: &Owner,
+    mut filter: Document,
+    page: Page,
+    criteria: SelectionCriteria,
+    placeholders: Placeholders,
+    shape: Shape,
+) -> AppResult<(Response, bool)> {
+    if let Some(cursor) = &page.cursor {
+        filter.insert("id", doc! { "$gt": cursor });
+    }
+
+    // One extra document tells whether another page follows
+    let collection = orders_collection();
+    let mut entities: Vec<_> = retry_read("GET /orders", || async {
+        collection
+            .find(filter.clone())
+            .sort(doc! { "id": 1 })
+            .max_time(get_config().db_max_time)
+            .selection_criteria(criteria.clone())
+            .limit(page.limit as i64 + 1)
+            .await?
+            .try_collect()
+            .await
+    })
+    .await
+    .map_err(AppError::database)?;
+
+    let next_cursor = if entities.len() > page.limit {
+        entities.truncate(page.limit);
+        entities.last().map(|e: &OrderEntity| e.id.clone())
+    } else {
+        None
+    };
+    let orders: Vec<OrderBody> = entities
+        .into_iter()
+        .map(|e| shape.apply(placeholders.apply(Order::from(e))))
+        .collect();
+
+    tracing::info!(target: targets::LIST, "GET /orders - user: {}, returning page of {} orders", owner.user_id, orders.len());
+    let empty = orders.is_empty() && page.cursor.is_none();
+    let mut response = Json(orders).into_response();
+    if let Some(id) = next_cursor {
+        let value = HeaderValue::from_str(&encode_cursor(&id)).expect("base64url is a valid header value");
+        response.headers_mut().insert("x-next-cursor", value);
+    }
+    Ok((response, empty))
+}
+
+/// `$gte`/`$lte` condition on `order_date_iso` for the requested range, if any
+fn order_date_range(query: &ListOrdersQuery, errors: &mut ValidationErrors) -> Option<Document> {
+    let mut parse = |name: &str, value: &Option<String>| {
+        let value = value.as_deref()?;
+        let date = parse_iso_date(value);
+        if date.is_none() {
+            errors.add(name, "must be a YYYY-MM-DD date");
+        }
+        date
+    };
+    let from = parse("from", &query.from);
+    let to = parse("to", &query.to);
+
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if from > to {
+            errors.add("from", "must not be after `to`");
+        }
+    }
+
+    let mut range = doc! {};
+    if let Some(from) = from {
+        range.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        range.insert("$lte", to);
+    }
+    (!range.is_empty()).then_some(range)
+}
+
+/// Parsed `updatedSince` timestamp, if given
+fn updated_since(query: &ListOrdersQuery, errors: &mut ValidationErrors) -> Option<bson::DateTime> {
+    let raw = query.updated_since.as_deref()?;
+    let since = bson::DateTime::parse_rfc3339_str(raw).ok();
+    if since.is_none() {
+        errors.add("updatedSince", "must be an RFC 3339 timestamp");
+    }
+    since
+}
+
+/// Serialize orders into a chunked JSON array as they come off the cursor.
+///
+/// The status line is already sent once streaming starts, so a cursor error
+/// mid-response can only be logged and the body aborted.
+fn stream_orders(cursor: Cursor<OrderEntity>, placeholders: Placeholders, shape: Shape) -> Response {
+    // Chunks are written after the handler returns, outside the request's format scope
+    let format = status_format::current();
+    let items = cursor.enumerate().map(move |(index, entity)| {
+        let entity = entity.inspect_err(|e| tracing::error!(target: targets::LIST, "GET /orders - stream aborted: {}", e))?;
+        let mut chunk = if index == 0 { Vec::new() } else { vec![b','] };
+        let order = shape.apply(placeholders.apply(Order::from(entity)));
+        status_format::scoped(format, || serde_json::to_writer(&mut chunk, &order)).expect("Order serializes to JSON");
+        Ok::<_, mongodb::error::Error>(Bytes::from(chunk))
+    });
+
+    let body = stream::once(async { Ok(Bytes::from_static(b"[")) })
+        .chain(items)
+        .chain(stream::once(async { Ok(Bytes::from_static(b"]")) }));
+
+    ([(header::CONTENT_TYPE, "application/json")], Body::from_stream(body)).into_response()
 }
 
 #[utoipa::path(
@@ -53,36 +606,212 @@ async fn list_orders(AuthUser(claims): AuthUser) -> AppResult<Json<Vec<Order>>>
     path = "/orders",
     tag = "Orders",
     summary = "Create a new order",
-    description = "Creates a new order for the authenticated user (upsert by order_number)",
+    description = "Creates a new order for the authenticated user (upsert by order_number, and by source when ORDER_SOURCES is on). \
+        `editableFields` locks are not enforced here, only by PATCH /orders/{id}.",
     request_body = CreateOrderRequest,
     responses(
         (status = 201, description = "Order created successfully", body = Order),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
 async fn create_order(
     AuthUser(claims): AuthUser,
-    Json(payload): Json<CreateOrderRequest>,
+    AppJson(mut payload): AppJson<CreateOrderRequest>,
 ) -> AppResult<(StatusCode, Json<Order>)> {
+    payload.normalize();
     tracing::info!(
+        target: targets::CREATE,
         "POST /orders - user: {}, order_number: {}",
         claims.sub,
         payload.order_number
     );
 
-    let entity = payload.into_entity(claims.sub);
+    payload.validate()?;
+    let owner = claims.owner();
+    let entity = payload.into_entity(&owner);
 
     // Upsert: update if exists, insert if not
-    let filter = doc! { "order_number": &entity.order_number, "user_id": &entity.user_id };
-    orders_collection()
-        .replace_one(filter, &entity)
+    let collection = orders_collection();
+    let filter = order_key(&owner, &entity.order_number, entity.source.as_deref());
+    let result = collection
+        .update_one(filter.clone(), upsert_update(&entity)?)
         .upsert(true)
         .await
         .map_err(AppError::database)?;
 
-    tracing::info!("POST /orders - upserted order: {}", entity.id);
-    Ok((StatusCode::CREATED, Json(Order::from(entity))))
+    let max_time = get_config().db_max_time;
+    let entity = retry_read("POST /orders", || collection.find_one(filter.clone()).max_time(max_time))
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+
+    let created = result.upserted_id.is_some();
+    tracing::info!(target: targets::CREATE, "POST /orders - {} order: {}", if created { "created" } else { "updated" }, entity.id);
+    let order = Order::from(entity);
+    let event = if created { OrderEventType::Created } else { OrderEventType::Updated };
+    webhooks::order_changed(event, &owner, order.clone());
+    Ok((StatusCode::CREATED, Json(order)))
+}
+
+/// Filter for the order an upsert targets: the owner's order with `order_number`, plus
+/// `source` when `ORDER_SOURCES` is on (an omitted source matches orders stored without one)
+fn order_key(owner: &Owner, order_number: &str, source: Option<&str>) -> Document {
+    let mut filter = owner.filter(doc! { "order_number": order_number });
+    if get_config().order_sources {
+        filter.insert("source", source);
+    }
+    filter
+}
+
+/// Update pipeline that upserts a full order like a replace would, except that an omitted
+/// `created_at` is kept (or set to the server time on insert) and the status timestamp
+/// only moves when the status changes. A pipeline, so it can compare the stored status.
+fn upsert_update(entity: &OrderEntity) -> AppResult<Vec<Document>> {
+    let mut set_doc = bson::to_document(entity).map_err(|e| AppError::Database(e.to_string()))?;
+    for field in ["commented_at", "revealed_at"] {
+        set_doc.remove(field);
+    }
+    // Other optional fields the client omitted are cleared, as a replace would
+    let unset: Vec<&str> = ["order_date_iso", "note", "tags", "updated_at", "deleted_at"]
+        .into_iter()
+        .filter(|field| !set_doc.contains_key(field))
+        .collect();
+    let keep_created = !set_doc.contains_key("created_at");
+
+    let mut set_stage = literal_fields(set_doc);
+    if keep_created {
+        set_stage.insert("created_at", doc! { "$ifNull": ["$created_at", now_rfc3339()] });
+    }
+    if let Some((field, value)) = reached_at_update(&entity.status) {
+        set_stage.insert(field, value);
+    }
+
+    let mut pipeline = vec![doc! { "$set": set_stage }];
+    if !unset.is_empty() {
+        pipeline.push(doc! { "$unset": unset });
+    }
+    Ok(pipeline)
+}
+
+/// Values for a pipeline `$set` stage, wrapped in `$literal` so client strings starting
+/// with `$` (prices like "$29.99") are stored as sent instead of read as field paths
+fn literal_fields(fields: Document) -> Document {
+    fields
+        .into_iter()
+        .map(|(field, value)| (field, bson::Bson::Document(doc! { "$literal": value })))
+        .collect()
+}
+
+/// Pipeline expression for the timestamp of the status being written: now when the stored
+/// status is a different one (or the order is new), the stored value otherwise
+fn reached_at_update(status: &OrderStatus) -> Option<(&'static str, bson::Bson)> {
+    let field = status.reached_at_field()?;
+    let value = doc! { "$cond": [{ "$eq": ["$status", status.as_str()] }, format!("${}", field), now_rfc3339()] };
+    Some((field, bson::Bson::Document(value)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/orders/by-number/{order_number}",
+    tag = "Orders",
+    summary = "Create or update an order by order number",
+    description = "Upserts the order with this order number for the authenticated user. \
+        `id` and `createdAt` are only applied when the order is created. `editableFields` locks are not \
+        enforced here, only by PATCH /orders/{id}.",
+    params(
+        ("order_number" = String, Path, description = "Amazon order number")
+    ),
+    request_body = UpsertOrderRequest,
+    responses(
+        (status = 200, description = "Existing order updated", body = Order),
+        (status = 201, description = "Order created", body = Order),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn upsert_order_by_number(
+    AuthUser(claims): AuthUser,
+    Path(mut order_number): Path<String>,
+    AppJson(mut payload): AppJson<UpsertOrderRequest>,
+) -> AppResult<(StatusCode, Json<Order>)> {
+    get_config().string_normalization.apply("orderNumber", &mut order_number);
+    payload.normalize();
+    tracing::info!(target: targets::UPSERT, "PUT /orders/by-number/{} - user: {}", order_number, claims.sub);
+
+    let mut errors = ValidationErrors::default();
+    if order_number.trim().is_empty() {
+        errors.add("orderNumber", "must not be empty");
+    }
+    check_order_number(&mut errors, "", payload.source.as_deref(), &order_number);
+    payload.validate_into("", &mut errors);
+    errors.into_result()?;
+
+    let mut set_doc = doc! {
+        "product_name": &payload.product_name,
+        "order_date": &payload.order_date,
+        "order_date_iso": normalize_order_date(&payload.order_date),
+        "product_image": &payload.product_image,
+        "price": &payload.price,
+        "status": payload.status.as_str(),
+        "tags": normalize_tags(&payload.tags),
+        "modified_at": bson::DateTime::now(),
+    };
+    if let Some(source) = &payload.source {
+        set_doc.insert("source", source);
+    }
+    if let Some(note) = &payload.note {
+        set_doc.insert("note", note);
+    }
+    if let Some(updated_at) = &payload.updated_at {
+        set_doc.insert("updated_at", updated_at);
+    }
+    if let Some(deleted_at) = &payload.deleted_at {
+        set_doc.insert("deleted_at", deleted_at);
+    }
+
+    // The owner fields and order_number are copied from the filter on insert; id and created_at
+    // are only written then
+    let id = payload.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let created_at = payload.created_at.unwrap_or_else(now_rfc3339);
+    let mut set_stage = literal_fields(set_doc);
+    set_stage.insert("id", doc! { "$ifNull": ["$id", { "$literal": id }] });
+    set_stage.insert("created_at", doc! { "$ifNull": ["$created_at", { "$literal": created_at }] });
+    if let Some((field, value)) = reached_at_update(&payload.status) {
+        set_stage.insert(field, value);
+    }
+
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let filter = order_key(&owner, &order_number, payload.source.as_deref());
+    let result = collection
+        .update_one(filter.clone(), vec![doc! { "$set": set_stage }])
+        .upsert(true)
+        .await
+        .map_err(AppError::database)?;
+
+    let max_time = get_config().db_max_time;
+    let entity = retry_read("PUT /orders/by-number", || collection.find_one(filter.clone()).max_time(max_time))
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+
+    let status = if result.upserted_id.is_some() {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    tracing::info!(target: targets::UPSERT, "PUT /orders/by-number/{} - {} order: {}", order_number,
+        if status == StatusCode::CREATED { "created" } else { "updated" }, entity.id);
+    let order = Order::from(entity);
+    let event = if status == StatusCode::CREATED { OrderEventType::Created } else { OrderEventType::Updated };
+    webhooks::order_changed(event, &owner, order.clone());
+    Ok((status, Json(order)))
 }
 
 #[utoipa::path(
@@ -90,47 +819,272 @@ async fn create_order(
     path = "/orders/batch",
     tag = "Orders",
     summary = "Batch upsert orders",
-    description = "Upserts multiple orders in a single request",
+    description = "Upserts multiple orders in a single request. With `mode=best-effort` (default) every \
+        order that can be written is, and failures are listed per item in `failed`. With `mode=atomic` \
+        the orders are written in one transaction (MongoDB must be a replica set or sharded cluster): if any \
+        fails, nothing is written and 409 lists the failures. \
+        `editableFields` locks are not enforced here, only by PATCH /orders/{id}.",
+    params(BatchUpsertQuery),
     request_body = BatchUpsertRequest,
     responses(
         (status = 200, description = "Batch upsert completed", body = BatchUpsertResponse),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 409, description = "Atomic batch rolled back", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
+        (status = 501, description = "`mode=atomic` on a MongoDB without transactions", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
 async fn batch_upsert_orders(
     AuthUser(claims): AuthUser,
-    Json(payload): Json<BatchUpsertRequest>,
+    Query(query): Query<BatchUpsertQuery>,
+    AppJson(mut payload): AppJson<BatchUpsertRequest>,
 ) -> AppResult<Json<BatchUpsertResponse>> {
+    payload.normalize();
+    payload.validate()?;
     let count = payload.orders.len();
-    if count > 100 {
-        return Err(AppError::bad_request("Batch size exceeds maximum of 100"));
-    }
-    tracing::info!("POST /orders/batch - user: {}, count: {}", claims.sub, count);
+    tracing::info!(target: targets::BATCH_UPSERT, "POST /orders/batch - user: {}, count: {}, mode: {:?}", claims.sub, count, query.mode);
 
     let collection = orders_collection();
     let mut models = Vec::with_capacity(count);
+    let mut order_numbers = Vec::with_capacity(count);
+    let mut order_ids = Vec::with_capacity(count);
 
+    let owner = claims.owner();
     for order_req in payload.orders {
-        let entity = order_req.into_entity(claims.sub.clone());
-        let filter = doc! { "order_number": &entity.order_number, "user_id": &entity.user_id };
-        let mut model = collection
-            .replace_one_model(filter, &entity)
-            .map_err(AppError::database)?;
-        model.upsert = Some(true);
-        models.push(model);
+        let entity = order_req.into_entity(&owner);
+        let filter = order_key(&owner, &entity.order_number, entity.source.as_deref());
+        let update = upsert_update(&entity)?;
+        models.push(
+            UpdateOneModel::builder()
+                .namespace(collection.namespace())
+                .filter(filter)
+                .update(update)
+                .upsert(true)
+                .build(),
+        );
+        order_numbers.push(entity.order_number);
+        order_ids.push(entity.id);
     }
 
-    let result = get_client()
-        .bulk_write(models)
-        .ordered(false)
+    // Written in chunks so very large imports do not build one huge command;
+    // unordered chunks keep going past individual failures
+    let chunk_size = get_config().bulk_write_chunk_size;
+    let mut totals = SummaryBulkWriteResult::default();
+    let mut failed = Vec::new();
+    // Per item: whether the write inserted the order, or `None` if it was not written
+    let mut created: Vec<Option<bool>> = vec![None; count];
+    let mut models = models.into_iter();
+    let mut offset = 0;
+
+    let mut session = match query.mode {
+        BatchMode::Atomic => {
+            let mut session = get_client().start_session().await.map_err(AppError::database)?;
+            session.start_transaction().await.map_err(AppError::database)?;
+            Some(session)
+        }
+        BatchMode::BestEffort => None,
+    };
+
+    loop {
+        let chunk: Vec<_> = models.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk_len = chunk.len();
+
+        // Verbose results tell which items were inserted, for the webhook event type
+        let outcome = match session.as_mut() {
+            Some(session) => get_client().bulk_write(chunk).verbose_results().session(session).await,
+            None => get_client().bulk_write(chunk).verbose_results().ordered(false).await,
+        };
+
+        match outcome {
+            Ok(result) => {
+                add_counts(&mut totals, &result.summary);
+                record_created(&mut created[offset..], &result.update_results);
+            }
+            Err(e) => {
+                if let Some(session) = session.as_mut() {
+                    if let Err(abort_err) = session.abort_transaction().await {
+                        tracing::warn!(target: targets::BATCH_UPSERT, "POST /orders/batch - abort failed: {}", abort_err);
+                    }
+                }
+                let ErrorKind::BulkWrite(bulk) = *e.kind else {
+                    return Err(AppError::database(e));
+                };
+                if bulk.write_errors.is_empty() {
+                    return Err(AppError::Database(format!("{:?}", bulk.write_concern_errors)));
+                }
+
+                let mut chunk_failed: Vec<BatchItemError> = bulk
+                    .write_errors
+                    .into_iter()
+                    .map(|(index, err)| BatchItemError {
+                        index: offset + index,
+                        order_number: order_numbers[offset + index].clone(),
+                        message: err.message,
+                    })
+                    .collect();
+                chunk_failed.sort_by_key(|f| f.index);
+
+                if session.is_some() {
+                    let fields = chunk_failed
+                        .into_iter()
+                        .map(|f| (format!("orders[{}]", f.index), vec![f.message]))
+                        .collect();
+                    return Err(AppError::BatchRejected(fields));
+                }
+                if let Some(PartialBulkWriteResult::Verbose(partial)) = bulk.partial_result {
+                    add_counts(&mut totals, &partial.summary);
+                    record_created(&mut created[offset..], &partial.update_results);
+                }
+                failed.extend(chunk_failed);
+            }
+        }
+        offset += chunk_len;
+    }
+
+    if let Some(mut session) = session {
+        session.commit_transaction().await.map_err(AppError::database)?;
+    }
+
+    if webhooks::enabled() {
+        report_batch(&owner, &order_ids, &created).await;
+    }
+
+    let upserted = totals.modified_count + totals.upserted_count + totals.inserted_count;
+    tracing::info!(target: targets::BATCH_UPSERT, "POST /orders/batch - upserted {} orders (inserted: {}, modified: {}, upserted: {}, failed: {})",
+        upserted, totals.inserted_count, totals.modified_count, totals.upserted_count, failed.len());
+    Ok(Json(BatchUpsertResponse {
+        upserted: upserted as usize,
+        created: totals.upserted_count as usize,
+        modified: totals.modified_count as usize,
+        failed,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/import/preview",
+    tag = "Orders",
+    summary = "Preview a batch import",
+    description = "Takes the same body as POST /orders/batch and reports, without writing anything, which \
+        orders would be created, which updated (with the changed fields) and which are unchanged. Orders are \
+        matched by order number, as the import does.",
+    request_body = BatchUpsertRequest,
+    responses(
+        (status = 200, description = "What the import would change", body = ImportPreview),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn preview_import(
+    AuthUser(claims): AuthUser,
+    AppJson(mut payload): AppJson<BatchUpsertRequest>,
+) -> AppResult<Json<ImportPreview>> {
+    payload.normalize();
+    payload.validate()?;
+    tracing::info!(target: targets::IMPORT_PREVIEW, "POST /orders/import/preview - user: {}, count: {}", claims.sub, payload.orders.len());
+
+    let owner = claims.owner();
+    let order_numbers: Vec<&str> = payload.orders.iter().map(|o| o.order_number.as_str()).collect();
+    let filter = owner.filter(doc! { "order_number": { "$in": &order_numbers } });
+    let collection = orders_collection();
+    let max_time = get_config().db_max_time;
+    let stored: Vec<OrderEntity> = retry_read("POST /orders/import/preview", || async {
+        collection
+            .find(filter.clone())
+            .max_time(max_time)
+            .selection_criteria(db::read_only())
+            .await?
+            .try_collect()
+            .await
+    })
         .await
         .map_err(AppError::database)?;
+    // Keyed like the import's upsert filter, so the same order is matched
+    let key = |order_number: &str, source: Option<&str>| {
+        (order_number.to_string(), source.filter(|_| get_config().order_sources).map(String::from))
+    };
+    let stored: HashMap<_, OrderEntity> = stored
+        .into_iter()
+        .map(|e| (key(&e.order_number, e.source.as_deref()), e))
+        .collect();
 
-    let upserted = result.modified_count + result.upserted_count + result.inserted_count;
-    tracing::info!("POST /orders/batch - upserted {} orders (inserted: {}, modified: {}, upserted: {})",
-        upserted, result.inserted_count, result.modified_count, result.upserted_count);
-    Ok(Json(BatchUpsertResponse { upserted: upserted as usize }))
+    let mut preview = ImportPreview { created: 0, updated: 0, unchanged: 0, items: Vec::with_capacity(order_numbers.len()) };
+    for (index, order_req) in payload.orders.into_iter().enumerate() {
+        let incoming = order_req.into_entity(&owner);
+        let changes = stored
+            .get(&key(&incoming.order_number, incoming.source.as_deref()))
+            .map(|current| current.import_changes(&incoming));
+        let action = match &changes {
+            None => ImportAction::Create,
+            Some(changes) if changes.is_empty() => ImportAction::Unchanged,
+            Some(_) => ImportAction::Update,
+        };
+        match action {
+            ImportAction::Create => preview.created += 1,
+            ImportAction::Update => preview.updated += 1,
+            ImportAction::Unchanged => preview.unchanged += 1,
+        }
+        preview.items.push(ImportPreviewItem {
+            index,
+            order_number: incoming.order_number,
+            action,
+            changes: changes.unwrap_or_default(),
+        });
+    }
+
+    tracing::info!(target: targets::IMPORT_PREVIEW, "POST /orders/import/preview - {} new, {} changed, {} unchanged",
+        preview.created, preview.updated, preview.unchanged);
+    Ok(Json(preview))
+}
+
+/// Note which items of a bulk write chunk were inserted rather than updated
+fn record_created(created: &mut [Option<bool>], results: &HashMap<usize, UpdateResult>) {
+    for (index, result) in results {
+        created[*index] = Some(result.upserted_id.is_some());
+    }
+}
+
+/// Send `order.created`/`order.updated` for the written items of a batch, with the orders
+/// as stored. The batch has already succeeded, so a failed read only loses the events.
+async fn report_batch(owner: &Owner, order_ids: &[String], created: &[Option<bool>]) {
+    let written: Vec<&String> = order_ids.iter().zip(created).filter(|(_, c)| c.is_some()).map(|(id, _)| id).collect();
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": { "$in": written } });
+    let max_time = get_config().db_max_time;
+    let entities: Vec<OrderEntity> = match retry_read("POST /orders/batch", || async {
+        collection.find(filter.clone()).max_time(max_time).await?.try_collect().await
+    })
+    .await
+    {
+        Ok(entities) => entities,
+        Err(e) => {
+            tracing::warn!(target: targets::BATCH_UPSERT, "POST /orders/batch - could not read orders for webhooks: {}", e);
+            return;
+        }
+    };
+
+    let mut by_id: HashMap<String, OrderEntity> = entities.into_iter().map(|e| (e.id.clone(), e)).collect();
+    for (id, created) in order_ids.iter().zip(created) {
+        let (Some(created), Some(entity)) = (created, by_id.remove(id)) else {
+            continue;
+        };
+        let event = if *created { OrderEventType::Created } else { OrderEventType::Updated };
+        webhooks::order_changed(event, owner, Order::from(entity));
+    }
+}
+
+fn add_counts(totals: &mut SummaryBulkWriteResult, result: &SummaryBulkWriteResult) {
+    totals.inserted_count += result.inserted_count;
+    totals.matched_count += result.matched_count;
+    totals.modified_count += result.modified_count;
+    totals.upserted_count += result.upserted_count;
 }
 
 #[utoipa::path(
@@ -142,54 +1096,192 @@ async fn batch_upsert_orders(
     request_body = BatchDeleteRequest,
     responses(
         (status = 200, description = "Batch delete completed", body = BatchDeleteResponse),
+        (status = 400, description = "Too many IDs", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
 async fn batch_delete_orders(
     AuthUser(claims): AuthUser,
-    Json(payload): Json<BatchDeleteRequest>,
+    AppJson(payload): AppJson<BatchDeleteRequest>,
 ) -> AppResult<Json<BatchDeleteResponse>> {
-    tracing::info!("POST /orders/batch-delete - user: {}, count: {}", claims.sub, payload.ids.len());
+    tracing::info!(target: targets::BATCH_DELETE, "POST /orders/batch-delete - user: {}, count: {}", claims.sub, payload.ids.len());
+    payload.validate()?;
 
-    let result = orders_collection()
-        .delete_many(doc! {
-            "id": { "$in": &payload.ids },
-            "user_id": &claims.sub,
-        })
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": { "$in": &payload.ids } });
+    // Tombstone only ids that actually existed
+    let max_time = get_config().db_max_time;
+    let existing: Vec<String> = retry_read("POST /orders/batch-delete", || collection.distinct("id", filter.clone()).max_time(max_time))
+        .await
+        .map_err(AppError::database)?
+        .into_iter()
+        .filter_map(|v| v.as_str().map(String::from))
+        .collect();
+
+    let result = collection
+        .delete_many(filter)
         .await
         .map_err(AppError::database)?;
 
-    tracing::info!("POST /orders/batch-delete - deleted {} orders", result.deleted_count);
+    record_tombstones(&owner, &existing).await?;
+    delete_images(&owner, &payload.ids).await?;
+    for id in &existing {
+        webhooks::order_event(OrderEventType::Deleted, &owner, id);
+    }
+
+    tracing::info!(target: targets::BATCH_DELETE, "POST /orders/batch-delete - deleted {} orders", result.deleted_count);
     Ok(Json(BatchDeleteResponse { deleted: result.deleted_count as usize }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/orders/batch-get",
+    tag = "Orders",
+    summary = "Batch get orders",
+    description = "Returns the caller's orders with the given IDs, in the order the IDs were requested. \
+        IDs that do not exist or belong to another user are left out.",
+    params(PlaceholderParams, StatusFormatParams, ShapeParams),
+    request_body = BatchGetRequest,
+    responses(
+        (status = 200, description = "Matching orders", body = Vec<OrderBody>),
+        (status = 400, description = "Too many IDs", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn batch_get_orders(
+    AuthUser(claims): AuthUser,
+    placeholders: Placeholders,
+    shape: Shape,
+    AppJson(payload): AppJson<BatchGetRequest>,
+) -> AppResult<Json<Vec<OrderBody>>> {
+    tracing::info!(target: targets::BATCH_GET, "POST /orders/batch-get - user: {}, count: {}", claims.sub, payload.ids.len());
+    payload.validate()?;
+
+    let collection = orders_collection();
+    let filter = claims.owner().filter(doc! { "id": { "$in": &payload.ids } });
+    let max_time = get_config().db_max_time;
+    let entities: Vec<OrderEntity> = retry_read("POST /orders/batch-get", || async {
+        collection
+            .find(filter.clone())
+            .max_time(max_time)
+            .selection_criteria(db::read_only())
+            .await?
+            .try_collect()
+            .await
+    })
+        .await
+        .map_err(AppError::database)?;
+
+    let mut by_id: HashMap<String, OrderEntity> = entities.into_iter().map(|e| (e.id.clone(), e)).collect();
+    let orders: Vec<OrderBody> = payload
+        .ids
+        .iter()
+        .filter_map(|id| by_id.remove(id))
+        .map(|e| shape.apply(placeholders.apply(Order::from(e))))
+        .collect();
+
+    tracing::info!(target: targets::BATCH_GET, "POST /orders/batch-get - found {} orders", orders.len());
+    Ok(Json(orders))
+}
+
 #[utoipa::path(
     get,
     path = "/orders/{id}",
     tag = "Orders",
     summary = "Get an order by ID",
     description = "Returns a specific order by its ID",
+    params(
+        ("id" = String, Path, description = "Order ID"),
+        PlaceholderParams,
+        StatusFormatParams,
+        ShapeParams
+    ),
+    responses(
+        (status = 200, description = "Order found", body = OrderBody,
+            headers(("ETag" = String, description = "Version of the order; send as `If-Match` to PATCH or DELETE it safely"))),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn get_order(
+    AuthUser(claims): AuthUser,
+    Path(id): Path<String>,
+    placeholders: Placeholders,
+    shape: Shape,
+) -> AppResult<Response> {
+    tracing::info!(target: targets::GET, "GET /orders/{} - user: {}", id, claims.sub);
+
+    let order = Order::from(find_order("GET /orders/{id}", &id, &claims.owner()).await?);
+    // Tagged before the placeholder is filled in, so the ETag still matches what If-Match checks
+    let etag = order.etag();
+    Ok(([(header::ETAG, etag)], Json(shape.apply(placeholders.apply(order)))).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/{id}/pdf",
+    tag = "Orders",
+    summary = "Download an order as PDF",
+    description = "Renders the order, including its product image, as a printable one-page PDF. \
+        If the image cannot be loaded, is too large or is not a supported format, the page notes it is unavailable.",
     params(
         ("id" = String, Path, description = "Order ID")
     ),
     responses(
-        (status = 200, description = "Order found", body = Order),
-        (status = 404, description = "Order not found"),
+        (status = 200, description = "PDF document", body = [u8], content_type = "application/pdf",
+            headers(("Content-Disposition" = String, description = "`attachment` with an `order-<number>.pdf` filename"))),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
-async fn get_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<Json<Order>> {
-    tracing::info!("GET /orders/{} - user: {}", id, claims.sub);
+async fn get_order_pdf(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<Response> {
+    tracing::info!(target: targets::PDF, "GET /orders/{}/pdf - user: {}", id, claims.sub);
 
-    let entity = orders_collection()
-        .find_one(doc! { "id": &id, "user_id": &claims.sub })
+    let owner = claims.owner();
+    let order = Order::from(find_order("GET /orders/{id}/pdf", &id, &owner).await?);
+    let image = load_product_image(&order, &owner).await;
+    let file_name = pdf::file_name(&order);
+    let bytes = tokio::task::spawn_blocking(move || pdf::render_order(&order, image.as_deref()))
         .await
-        .map_err(AppError::database)?
-        .ok_or_else(|| AppError::not_found("Order"))?;
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(AppError::Internal)?;
 
-    Ok(Json(Order::from(entity)))
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+/// Fetch one of the caller's orders, distinguishing deleted orders from unknown ones
+async fn find_order(operation: &str, id: &str, owner: &Owner) -> AppResult<OrderEntity> {
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": id });
+    let max_time = get_config().db_max_time;
+    let Some(entity) = retry_read(operation, || {
+        collection
+            .find_one(filter.clone())
+            .max_time(max_time)
+            .selection_criteria(db::read_only())
+    })
+        .await
+        .map_err(AppError::database)?
+    else {
+        return Err(missing_order(id, owner).await);
+    };
+    Ok(entity)
 }
 
 #[utoipa::path(
@@ -197,15 +1289,25 @@ async fn get_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppRes
     path = "/orders/{id}",
     tag = "Orders",
     summary = "Update an order",
-    description = "Updates an existing order's status or note",
+    description = "Updates an existing order's status or note. Only fields that differ from the stored order are written. \
+        Send `Prefer: return=representation` to receive the updated order in the response body. \
+        With `If-Match`, the update only applies if the order still has that ETag. \
+        Changing a field missing from the order's `editableFields` fails validation, and 409 is returned if the \
+        status changes while the update runs.",
     params(
-        ("id" = String, Path, description = "Order ID")
+        ("id" = String, Path, description = "Order ID"),
+        ("Prefer" = Option<String>, Header, description = "`return=representation` to return the updated order"),
+        ("If-Match" = Option<String>, Header, description = "ETag from GET /orders/{id}, or `*`")
     ),
     request_body = UpdateOrderRequest,
     responses(
-        (status = 200, description = "Order updated successfully"),
-        (status = 400, description = "Bad request (empty update)"),
-        (status = 404, description = "Order not found"),
+        (status = 200, description = "Order updated successfully (body only with Prefer: return=representation)", body = Order),
+        (status = 400, description = "Bad request (empty update, validation failed or field locked by status)", body = ApiError),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 409, description = "Order status changed concurrently", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 412, description = "If-Match does not match the current order", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
@@ -213,49 +1315,272 @@ async fn get_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppRes
 async fn update_order(
     AuthUser(claims): AuthUser,
     Path(id): Path<String>,
-    Json(payload): Json<UpdateOrderRequest>,
-) -> AppResult<StatusCode> {
-    tracing::info!("PATCH /orders/{} - user: {}", id, claims.sub);
+    headers: HeaderMap,
+    AppJson(mut payload): AppJson<UpdateOrderRequest>,
+) -> AppResult<Response> {
+    payload.normalize();
+    tracing::info!(target: targets::UPDATE, "PATCH /orders/{} - user: {}", id, claims.sub);
+
+    if payload.is_empty() {
+        return Err(AppError::bad_request("No fields to update"));
+    }
+    payload.validate()?;
 
-    let mut update_doc = doc! {};
+    // Read first so only genuinely changed fields are written
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": &id });
+    let max_time = get_config().db_max_time;
+    let Some(current) = retry_read("PATCH /orders/{id}", || collection.find_one(filter.clone()).max_time(max_time))
+        .await
+        .map_err(AppError::database)?
+    else {
+        return Err(missing_order(&id, &owner).await);
+    };
+    let conditional = check_if_match(&headers, &current)?;
+    let filter = update_filter(filter, &current, conditional);
 
-    if let Some(status) = &payload.status {
-        let status_str = match status {
-            OrderStatus::Uncommented => "uncommented",
-            OrderStatus::Commented => "commented",
-            OrderStatus::CommentRevealed => "comment_revealed",
-            OrderStatus::Reimbursed => "reimbursed",
-        };
-        update_doc.insert("status", status_str);
+    let return_representation = prefers_representation(&headers);
+    let mut changes = payload.changes_from(&current);
+    check_editable(&changes, &current)?;
+    if changes.is_empty() {
+        tracing::info!(target: targets::UPDATE, "PATCH /orders/{} - no changes", id);
+        return Ok(update_response(return_representation.then_some(current)));
     }
-    if let Some(note) = &payload.note {
-        update_doc.insert("note", note);
+    let changed_fields: Vec<String> = changes.keys().cloned().collect();
+    changes.insert("modified_at", bson::DateTime::now());
+    let update = doc! { "$set": changes };
+
+    // Webhooks report the updated order, so it is read back even if the client skips it
+    let updated = if return_representation || webhooks::enabled() {
+        // One round trip for both the write and the post-update document
+        let entity = collection
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .max_time(max_time)
+            .await
+            .map_err(AppError::database)?;
+        let Some(entity) = entity else {
+            return Err(update_missed(conditional, &id, &owner).await);
+        };
+        Some(entity)
+    } else {
+        let result = collection
+            .update_one(filter, update)
+            .await
+            .map_err(AppError::database)?;
+        if result.matched_count == 0 {
+            return Err(update_missed(conditional, &id, &owner).await);
+        }
+        None
+    };
+
+    tracing::info!(target: targets::UPDATE, "PATCH /orders/{} - updated {:?}", id, changed_fields);
+    if let Some(entity) = &updated {
+        webhooks::order_changed(OrderEventType::Updated, &owner, Order::from(entity.clone()));
     }
-    if let Some(updated_at) = &payload.updated_at {
-        update_doc.insert("updated_at", updated_at);
+    Ok(update_response(updated.filter(|_| return_representation)))
+}
+
+/// The PATCH write filter. Locks are checked against the status that was read, so the
+/// write is pinned to it: a concurrent status change makes it miss rather than edit a
+/// field the new status locks. Under `If-Match` the whole version read is pinned.
+fn update_filter(filter: Document, current: &OrderEntity, conditional: bool) -> Document {
+    let mut filter = if conditional { unchanged_filter(filter, current) } else { filter };
+    filter.insert("status", current.status.as_str());
+    filter
+}
+
+/// Whether the client sent `Prefer: return=representation` (RFC 7240)
+fn prefers_representation(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|pref| pref.trim().eq_ignore_ascii_case("return=representation"))
+}
+
+fn update_response(entity: Option<OrderEntity>) -> Response {
+    match entity {
+        Some(entity) => {
+            let order = Order::from(entity);
+            (
+                [("preference-applied", "return=representation".to_string()), ("etag", order.etag())],
+                Json(order),
+            )
+                .into_response()
+        }
+        None => StatusCode::OK.into_response(),
     }
-    if let Some(deleted_at) = &payload.deleted_at {
-        update_doc.insert("deleted_at", deleted_at);
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/archive",
+    tag = "Orders",
+    summary = "Archive an order",
+    description = "Hides an order from the default order list without deleting it",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Order archived", body = Order),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn archive_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<Json<Order>> {
+    tracing::info!(target: targets::ARCHIVE, "POST /orders/{}/archive - user: {}", id, claims.sub);
+    set_archived(&claims.owner(), &id, true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/unarchive",
+    tag = "Orders",
+    summary = "Unarchive an order",
+    description = "Returns an archived order to the default order list",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 200, description = "Order unarchived", body = Order),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn unarchive_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<Json<Order>> {
+    tracing::info!(target: targets::ARCHIVE, "POST /orders/{}/unarchive - user: {}", id, claims.sub);
+    set_archived(&claims.owner(), &id, false).await
+}
+
+async fn set_archived(owner: &Owner, id: &str, archived: bool) -> AppResult<Json<Order>> {
+    let update = if archived {
+        doc! { "$set": { "archived": true, "modified_at": bson::DateTime::now() } }
+    } else {
+        doc! { "$unset": { "archived": "" }, "$set": { "modified_at": bson::DateTime::now() } }
+    };
+    let Some(entity) = orders_collection()
+        .find_one_and_update(owner.filter(doc! { "id": id }), update)
+        .return_document(ReturnDocument::After)
+        .max_time(get_config().db_max_time)
+        .await
+        .map_err(AppError::database)?
+    else {
+        return Err(missing_order(id, owner).await);
+    };
+
+    let order = Order::from(entity);
+    webhooks::order_changed(OrderEventType::Updated, owner, order.clone());
+    Ok(Json(order))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/rekey",
+    tag = "Orders",
+    summary = "Change an order's ID",
+    description = "Moves an order to a new ID, keeping every other field and its uploaded image. \
+        The old ID answers 410 afterwards, so syncing clients drop their copy and pick up the order \
+        under its new ID. Runs in a transaction, so MongoDB must be a replica set or sharded cluster.",
+    params(
+        ("id" = String, Path, description = "Current order ID")
+    ),
+    request_body = RekeyOrderRequest,
+    responses(
+        (status = 200, description = "Order moved to the new ID", body = Order),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 409, description = "Another order already has the new ID", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 501, description = "MongoDB does not support transactions", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn rekey_order(
+    AuthUser(claims): AuthUser,
+    Path(id): Path<String>,
+    AppJson(mut payload): AppJson<RekeyOrderRequest>,
+) -> AppResult<Json<Order>> {
+    payload.normalize();
+    payload.validate()?;
+    let new_id = payload.new_id;
+    tracing::info!(target: targets::REKEY, "POST /orders/{}/rekey - user: {}, new id: {}", id, claims.sub, new_id);
+    if new_id == id {
+        return Err(AppError::bad_request("New ID is the order's current ID"));
     }
 
-    if update_doc.is_empty() {
-        return Err(AppError::bad_request("No fields to update"));
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let max_time = get_config().db_max_time;
+    let mut session = get_client().start_session().await.map_err(AppError::database)?;
+    session.start_transaction().await.map_err(AppError::database)?;
+
+    // The uniqueness check and the move run in one transaction, so a concurrent write to
+    // either ID aborts it instead of leaving two orders with the same ID
+    let taken = collection
+        .find_one(owner.filter(doc! { "id": &new_id }))
+        .max_time(max_time)
+        .session(&mut session)
+        .await
+        .map_err(AppError::database)?;
+    if taken.is_some() {
+        return Err(AppError::conflict(format!("Another order already has ID {}", new_id)));
     }
 
-    let result = orders_collection()
+    let Some(current) = collection
+        .find_one(owner.filter(doc! { "id": &id }))
+        .max_time(max_time)
+        .session(&mut session)
+        .await
+        .map_err(AppError::database)?
+    else {
+        return Err(missing_order(&id, &owner).await);
+    };
+
+    let mut changes = doc! { "id": &new_id, "modified_at": bson::DateTime::now() };
+    let moves_image = current.product_image == format!("/orders/{}/image", id);
+    if moves_image {
+        changes.insert("product_image", format!("/orders/{}/image", new_id));
+    }
+    let entity = collection
+        .find_one_and_update(owner.filter(doc! { "id": &id }), doc! { "$set": changes })
+        .return_document(ReturnDocument::After)
+        .max_time(max_time)
+        .session(&mut session)
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+    tombstones_collection()
         .update_one(
-            doc! { "id": &id, "user_id": &claims.sub },
-            doc! { "$set": update_doc },
+            owner.filter(doc! { "id": &id }),
+            doc! { "$set": { "purged_at": bson::DateTime::now(), "rekeyed_to": &new_id } },
         )
+        .upsert(true)
+        .session(&mut session)
         .await
         .map_err(AppError::database)?;
+    session.commit_transaction().await.map_err(AppError::database)?;
 
-    if result.matched_count == 0 {
-        return Err(AppError::not_found("Order"));
+    // The move is committed; a failed image rename only leaves the image under the old id
+    if moves_image {
+        if let Err(e) = rename_images(&owner, &id, &new_id).await {
+            tracing::warn!(target: targets::REKEY, "POST /orders/{}/rekey - image not moved to {}: {:?}", id, new_id, e);
+        }
     }
 
-    tracing::info!("PATCH /orders/{} - updated", id);
-    Ok(StatusCode::OK)
+    tracing::info!(target: targets::REKEY, "POST /orders/{}/rekey - user: {}, moved to {}", id, claims.sub, new_id);
+    // Receivers key orders by id, so the move is reported as the old id going away
+    let order = Order::from(entity);
+    webhooks::order_event(OrderEventType::Deleted, &owner, &id);
+    webhooks::order_changed(OrderEventType::Created, &owner, order.clone());
+    Ok(Json(order))
 }
 
 #[utoipa::path(
@@ -263,29 +1588,558 @@ async fn update_order(
     path = "/orders/{id}",
     tag = "Orders",
     summary = "Delete an order",
-    description = "Deletes an order by its ID",
+    description = "Deletes an order by its ID. With `If-Match`, the order is only deleted if it still has that ETag.",
     params(
-        ("id" = String, Path, description = "Order ID")
+        ("id" = String, Path, description = "Order ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag from GET /orders/{id}, or `*`")
     ),
     responses(
         (status = 204, description = "Order deleted successfully"),
-        (status = 404, description = "Order not found"),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 410, description = "Order was permanently deleted", body = ApiError),
+        (status = 412, description = "If-Match does not match the current order", body = ApiError),
         (status = 401, description = "Unauthorized", body = AuthError)
     ),
     security(("bearer_auth" = []))
 )]
-async fn delete_order(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<StatusCode> {
-    tracing::info!("DELETE /orders/{} - user: {}", id, claims.sub);
+async fn delete_order(
+    AuthUser(claims): AuthUser,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> AppResult<StatusCode> {
+    tracing::info!(target: targets::DELETE, "DELETE /orders/{} - user: {}", id, claims.sub);
+
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let mut filter = owner.filter(doc! { "id": &id });
+    let conditional = headers.contains_key(header::IF_MATCH);
+    if conditional {
+        let max_time = get_config().db_max_time;
+        let Some(current) = retry_read("DELETE /orders/{id}", || collection.find_one(filter.clone()).max_time(max_time))
+            .await
+            .map_err(AppError::database)?
+        else {
+            return Err(missing_order(&id, &owner).await);
+        };
+        check_if_match(&headers, &current)?;
+        filter = unchanged_filter(filter, &current);
+    }
 
-    let result = orders_collection()
-        .delete_one(doc! { "id": &id, "user_id": &claims.sub })
+    let result = collection
+        .delete_one(filter)
         .await
         .map_err(AppError::database)?;
 
     if result.deleted_count == 0 {
-        return Err(AppError::not_found("Order"));
+        return Err(if conditional {
+            write_missed(true)
+        } else {
+            missing_order(&id, &owner).await
+        });
     }
 
-    tracing::info!("DELETE /orders/{} - deleted", id);
+    record_tombstones(&owner, std::slice::from_ref(&id)).await?;
+    delete_images(&owner, std::slice::from_ref(&id)).await?;
+    webhooks::order_event(OrderEventType::Deleted, &owner, &id);
+
+    tracing::info!(target: targets::DELETE, "DELETE /orders/{} - deleted", id);
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Enforce `If-Match` against the stored order; returns whether the write must be conditional
+fn check_if_match(headers: &HeaderMap, current: &OrderEntity) -> AppResult<bool> {
+    let tags: Vec<&str> = headers
+        .get_all(header::IF_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    if tags.is_empty() {
+        return Ok(false);
+    }
+
+    let etag = Order::from(current.clone()).etag();
+    if tags.iter().any(|t| *t == "*" || *t == etag) {
+        Ok(true)
+    } else {
+        Err(AppError::precondition_failed("Order has changed; fetch it again for the current ETag"))
+    }
+}
+
+/// Narrow a write filter to the state that was just read, so a concurrent write makes it miss
+fn unchanged_filter(mut filter: Document, current: &OrderEntity) -> Document {
+    match current.modified_at {
+        Some(modified_at) => filter.insert("modified_at", modified_at),
+        None => filter.insert("modified_at", doc! { "$exists": false }),
+    };
+    filter
+}
+
+/// Error for a write that matched nothing after the order was read
+fn write_missed(conditional: bool) -> AppError {
+    if conditional {
+        AppError::precondition_failed("Order was changed concurrently; fetch it again for the current ETag")
+    } else {
+        AppError::not_found("Order")
+    }
+}
+
+/// Error for a PATCH that matched nothing after the order was read: 412 under `If-Match`,
+/// 409 when the order is still there (its status changed), otherwise 404/410
+async fn update_missed(conditional: bool, id: &str, owner: &Owner) -> AppError {
+    if conditional {
+        return write_missed(true);
+    }
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": id });
+    let max_time = get_config().db_max_time;
+    match retry_read("PATCH /orders/{id}", || collection.find_one(filter.clone()).max_time(max_time)).await {
+        Ok(Some(_)) => AppError::conflict("Order status was changed concurrently; fetch it again and retry"),
+        Ok(None) => missing_order(id, owner).await,
+        Err(e) => AppError::database(e),
+    }
+}
+
+/// 410 when the order was hard-deleted (a tombstone exists), 404 when it never existed
+async fn missing_order(id: &str, owner: &Owner) -> AppError {
+    let tombstones = tombstones_collection();
+    let filter = owner.filter(doc! { "id": id });
+    let max_time = get_config().db_max_time;
+    match retry_read("tombstone lookup", || tombstones.find_one(filter.clone()).max_time(max_time)).await {
+        Ok(Some(_)) => AppError::gone("Order"),
+        Ok(None) => AppError::not_found("Order"),
+        Err(e) => AppError::database(e),
+    }
+}
+
+/// Remember hard-deleted order ids so later lookups can answer 410 Gone
+pub async fn record_tombstones(owner: &Owner, ids: &[String]) -> AppResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let tombstones = tombstones_collection();
+    let purged_at = bson::DateTime::now();
+    let models = ids.iter().map(|id| {
+        UpdateOneModel::builder()
+            .namespace(tombstones.namespace())
+            .filter(owner.filter(doc! { "id": id }))
+            .update(doc! { "$set": { "purged_at": purged_at } })
+            .upsert(true)
+            .build()
+    });
+
+    get_client()
+        .bulk_write(models)
+        .ordered(false)
+        .await
+        .map_err(AppError::database)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Claims;
+    use crate::db::test_support::{run, supports_transactions, unique_user};
+    use mongodb::{options::IndexOptions, IndexModel};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn create_request(order_number: &str, status: &str) -> CreateOrderRequest {
+        CreateOrderRequest::fixture(json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "orderNumber": order_number,
+            "status": status,
+        }))
+    }
+
+    fn upsert_request(status: &str) -> UpsertOrderRequest {
+        let mut body = CreateOrderRequest::fixture_json(json!({ "status": status }));
+        let fields = body.as_object_mut().unwrap();
+        fields.remove("id");
+        fields.remove("orderNumber");
+        serde_json::from_value(body).unwrap()
+    }
+
+    /// Write an order the way `POST /orders` and `POST /orders/batch` do
+    async fn upsert(user_id: &str, request: CreateOrderRequest) {
+        let owner = Claims::for_user(user_id).owner();
+        let entity = request.into_entity(&owner);
+        let filter = order_key(&owner, &entity.order_number, entity.source.as_deref());
+        orders_collection()
+            .update_one(filter, upsert_update(&entity).unwrap())
+            .upsert(true)
+            .await
+            .unwrap();
+    }
+
+    async fn stored(user_id: &str, order_number: &str) -> OrderEntity {
+        orders_collection()
+            .find_one(doc! { "user_id": user_id, "order_number": order_number })
+            .await
+            .unwrap()
+            .expect("order is stored")
+    }
+
+    #[test]
+    fn upsert_pipeline_writes_client_values_literally() {
+        crate::config::init_test_config();
+        let entity = create_request("123-4567890-1234567", "commented").into_entity(&Claims::for_user("user").owner());
+        let pipeline = upsert_update(&entity).unwrap();
+        let set = pipeline[0].get_document("$set").unwrap();
+
+        assert_eq!(set.get_document("price").unwrap(), &doc! { "$literal": "$29.99" });
+        assert!(set.get_document("commented_at").unwrap().contains_key("$cond"));
+        assert!(!set.contains_key("revealed_at"));
+    }
+
+    fn if_match(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn entity() -> OrderEntity {
+        crate::config::init_test_config();
+        create_request("123-4567890-1234567", "uncommented").into_entity(&Claims::for_user("user").owner())
+    }
+
+    #[test]
+    fn writes_without_if_match_are_unconditional() {
+        assert!(!check_if_match(&HeaderMap::new(), &entity()).unwrap());
+    }
+
+    #[test]
+    fn if_match_accepts_the_current_etag_or_a_wildcard() {
+        let current = entity();
+        let etag = Order::from(current.clone()).etag();
+        assert!(check_if_match(&if_match(&[&etag]), &current).unwrap());
+        assert!(check_if_match(&if_match(&["*"]), &current).unwrap());
+        // Lists, in one header or several, match if any entry does
+        assert!(check_if_match(&if_match(&[&format!("\"stale\", {}", etag)]), &current).unwrap());
+        assert!(check_if_match(&if_match(&["\"stale\"", &etag]), &current).unwrap());
+    }
+
+    #[test]
+    fn if_match_with_another_etag_fails_the_precondition() {
+        let current = entity();
+        let mut changed = current.clone();
+        changed.status = OrderStatus::Commented;
+        let stale = Order::from(changed).etag();
+        assert!(matches!(check_if_match(&if_match(&[&stale]), &current), Err(AppError::PreconditionFailed(_))));
+        assert!(matches!(check_if_match(&if_match(&["\"stale\""]), &current), Err(AppError::PreconditionFailed(_))));
+    }
+
+    #[test]
+    fn batch_items_are_reported_as_created_or_updated() {
+        let mut inserted = UpdateResult::default();
+        inserted.upserted_id = Some(bson::Bson::Int32(1));
+        let mut updated = UpdateResult::default();
+        updated.matched_count = 1;
+        let mut created = vec![None; 4];
+        // Chunk indexes are relative to the chunk; index 1 of the chunk failed
+        record_created(&mut created[1..], &HashMap::from([(0, inserted), (2, updated)]));
+        assert_eq!(created, [None, Some(true), None, Some(false)]);
+    }
+
+    #[test]
+    fn updates_are_pinned_to_the_status_that_was_read() {
+        let mut current = entity();
+        current.status = OrderStatus::Commented;
+        assert_eq!(update_filter(doc! { "id": "order-1" }, &current, false), doc! { "id": "order-1", "status": "commented" });
+
+        let modified_at = current.modified_at.unwrap();
+        assert_eq!(
+            update_filter(doc! { "id": "order-1" }, &current, true),
+            doc! { "id": "order-1", "modified_at": modified_at, "status": "commented" }
+        );
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn missed_updates_tell_a_status_change_from_a_deletion() {
+        run(async {
+            let user = unique_user();
+            let owner = Claims::for_user(&user).owner();
+            let request = create_request("123-4567890-1234567", "reimbursed");
+            let id = request.id.clone();
+            upsert(&user, request).await;
+
+            assert!(matches!(update_missed(false, &id, &owner).await, AppError::Conflict(_)));
+            assert!(matches!(update_missed(true, &id, &owner).await, AppError::PreconditionFailed(_)));
+            assert!(matches!(update_missed(false, "missing", &owner).await, AppError::NotFound(_)));
+        });
+    }
+
+    #[test]
+    fn unchanged_filter_pins_the_version_that_was_read() {
+        let mut current = entity();
+        let modified_at = current.modified_at.unwrap();
+        assert_eq!(
+            unchanged_filter(doc! { "id": "order-1" }, &current),
+            doc! { "id": "order-1", "modified_at": modified_at }
+        );
+
+        // Orders written before modified_at existed only match while they still lack it
+        current.modified_at = None;
+        assert_eq!(
+            unchanged_filter(doc! { "id": "order-1" }, &current),
+            doc! { "id": "order-1", "modified_at": { "$exists": false } }
+        );
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn upsert_stamps_an_existing_order_moving_to_commented() {
+        run(async {
+            let user = unique_user();
+            upsert(&user, create_request("111-0000000-0000001", "uncommented")).await;
+            let order = stored(&user, "111-0000000-0000001").await;
+            assert_eq!((order.commented_at, order.created_at.is_some()), (None, true));
+
+            upsert(&user, create_request("111-0000000-0000001", "commented")).await;
+            let commented_at = stored(&user, "111-0000000-0000001").await.commented_at;
+            assert!(commented_at.is_some());
+
+            // Re-sending the same status keeps the first timestamp
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            upsert(&user, create_request("111-0000000-0000001", "commented")).await;
+            let order = stored(&user, "111-0000000-0000001").await;
+            assert_eq!(order.commented_at, commented_at);
+            assert_eq!(order.price, "$29.99");
+        });
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn upsert_by_number_stamps_an_existing_order_on_each_status_change() {
+        run(async {
+            let user = unique_user();
+            let put = |status: &'static str| {
+                let claims = Claims::for_user(&user);
+                async move {
+                    upsert_order_by_number(AuthUser(claims), Path("222-0000000-0000002".to_string()), AppJson(upsert_request(status)))
+                        .await
+                        .unwrap()
+                        .0
+                }
+            };
+
+            assert_eq!(put("uncommented").await, StatusCode::CREATED);
+            assert_eq!(put("commented").await, StatusCode::OK);
+            let order = stored(&user, "222-0000000-0000002").await;
+            let (id, commented_at) = (order.id, order.commented_at);
+            assert!(commented_at.is_some());
+            assert_eq!(order.revealed_at, None);
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            put("comment_revealed").await;
+            let order = stored(&user, "222-0000000-0000002").await;
+            assert!(order.revealed_at.is_some());
+            assert_eq!(order.commented_at, commented_at);
+            assert_eq!(order.id, id);
+        });
+    }
+
+    async fn batch(user_id: &str, mode: &str, orders: Vec<CreateOrderRequest>) -> AppResult<BatchUpsertResponse> {
+        let query = serde_json::from_value(json!({ "mode": mode })).unwrap();
+        let Json(response) =
+            batch_upsert_orders(AuthUser(Claims::for_user(user_id)), Query(query), AppJson(BatchUpsertRequest { orders }))
+                .await?;
+        Ok(response)
+    }
+
+    /// Name of the index [`batch_with_conflict`] adds for `user_id`
+    fn conflict_index(user_id: &str) -> String {
+        format!("idx_test_id_unique_{}", user_id)
+    }
+
+    /// Three new orders where the middle one reuses `taken_id`. The user's order ids get a
+    /// unique index here so that it fails with a duplicate-key error, as any unique-key
+    /// clash would; it is partial so other tests' orders are unaffected. Drop it with
+    /// [`drop_conflict_index`].
+    async fn batch_with_conflict(user_id: &str, taken_id: &str) -> Vec<CreateOrderRequest> {
+        let options = IndexOptions::builder()
+            .unique(true)
+            .name(conflict_index(user_id))
+            .partial_filter_expression(doc! { "user_id": user_id })
+            .build();
+        let index = IndexModel::builder().keys(doc! { "id": 1 }).options(options).build();
+        orders_collection().create_index(index).await.unwrap();
+
+        let mut conflicting = create_request("444-0000000-0000002", "uncommented");
+        conflicting.id = taken_id.to_string();
+        vec![
+            create_request("444-0000000-0000001", "uncommented"),
+            conflicting,
+            create_request("444-0000000-0000003", "uncommented"),
+        ]
+    }
+
+    async fn drop_conflict_index(user_id: &str) {
+        orders_collection().drop_index(conflict_index(user_id)).await.unwrap();
+    }
+
+    async fn stored_numbers(user_id: &str) -> Vec<String> {
+        let mut numbers: Vec<String> = orders_collection()
+            .distinct("order_number", doc! { "user_id": user_id })
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect();
+        numbers.sort();
+        numbers
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn best_effort_batch_writes_around_a_duplicate_key() {
+        run(async {
+            let user = unique_user();
+            upsert(&user, create_request("444-0000000-0000000", "uncommented")).await;
+            let taken_id = stored(&user, "444-0000000-0000000").await.id;
+
+            let response = batch(&user, "best-effort", batch_with_conflict(&user, &taken_id).await).await;
+            drop_conflict_index(&user).await;
+            let response = response.unwrap();
+            assert_eq!((response.upserted, response.created, response.modified), (2, 2, 0));
+            assert_eq!(response.failed.len(), 1);
+            assert_eq!((response.failed[0].index, response.failed[0].order_number.as_str()), (1, "444-0000000-0000002"));
+            assert!(response.failed[0].message.contains("duplicate key"), "{}", response.failed[0].message);
+            assert_eq!(stored_numbers(&user).await, ["444-0000000-0000000", "444-0000000-0000001", "444-0000000-0000003"]);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a replica set (MONGODB_URI)"]
+    fn atomic_batch_writes_nothing_after_a_duplicate_key_on_a_replica_set() {
+        run(async {
+            assert!(supports_transactions().await, "atomic batches need MONGODB_URI to point at a replica set");
+            let user = unique_user();
+            upsert(&user, create_request("444-0000000-0000000", "uncommented")).await;
+            let taken_id = stored(&user, "444-0000000-0000000").await.id;
+
+            let result = batch(&user, "atomic", batch_with_conflict(&user, &taken_id).await).await;
+            drop_conflict_index(&user).await;
+            let Err(AppError::BatchRejected(fields)) = result else {
+                panic!("atomic batch with a conflict must be rejected");
+            };
+            assert_eq!(fields.keys().collect::<Vec<_>>(), ["orders[1]"]);
+            assert_eq!(stored_numbers(&user).await, ["444-0000000-0000000"]);
+
+            // Without the conflict the same batch commits in full
+            let orders = vec![create_request("444-0000000-0000001", "uncommented"), create_request("444-0000000-0000003", "commented")];
+            let response = batch(&user, "atomic", orders).await.unwrap();
+            assert_eq!((response.created, response.failed.len()), (2, 0));
+            assert_eq!(stored_numbers(&user).await, ["444-0000000-0000000", "444-0000000-0000001", "444-0000000-0000003"]);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a standalone MongoDB (just db)"]
+    fn transactions_are_refused_clearly_on_a_standalone_server() {
+        run(async {
+            assert!(!supports_transactions().await, "MONGODB_URI points at a replica set");
+            let user = unique_user();
+            let orders = vec![create_request("666-0000000-0000001", "uncommented")];
+            assert!(matches!(batch(&user, "atomic", orders).await, Err(AppError::TransactionsUnsupported)));
+
+            upsert(&user, create_request("666-0000000-0000002", "uncommented")).await;
+            let id = stored(&user, "666-0000000-0000002").await.id;
+            let request = RekeyOrderRequest { new_id: uuid::Uuid::new_v4().to_string() };
+            let result = rekey_order(AuthUser(Claims::for_user(&user)), Path(id), AppJson(request)).await;
+            assert!(matches!(result, Err(AppError::TransactionsUnsupported)));
+        });
+    }
+
+    #[test]
+    fn client_user_ids_are_ignored() {
+        crate::config::init_test_config();
+        let mut body = CreateOrderRequest::fixture_json(json!({ "userId": "someone-else" }));
+        let request: CreateOrderRequest = serde_json::from_value(body.clone()).unwrap();
+        assert_eq!(request.into_entity(&Claims::for_user("token-user").owner()).user_id, "token-user");
+
+        // Any JSON value is accepted in its place
+        body["userId"] = json!({ "$ne": null });
+        assert!(serde_json::from_value::<CreateOrderRequest>(body).is_ok());
+        assert!(serde_json::from_value::<UpdateOrderRequest>(json!({ "userId": 7, "note": "n" })).is_ok());
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn orders_are_stored_under_the_token_user() {
+        run(async {
+            let (user, other) = (unique_user(), unique_user());
+            let mut body = CreateOrderRequest::fixture_json(json!({
+                "userId": &other,
+                "id": uuid::Uuid::new_v4().to_string(),
+                "orderNumber": "555-0000000-0000005",
+            }));
+            let request = serde_json::from_value(body.clone()).unwrap();
+            let (_, Json(created)) = create_order(AuthUser(Claims::for_user(&user)), AppJson(request)).await.unwrap();
+
+            body.as_object_mut().unwrap().remove("orderNumber");
+            let request = serde_json::from_value(body).unwrap();
+            let (_, Json(upserted)) =
+                upsert_order_by_number(AuthUser(Claims::for_user(&user)), Path("555-0000000-0000006".to_string()), AppJson(request))
+                    .await
+                    .unwrap();
+
+            assert_eq!(stored(&user, "555-0000000-0000005").await.id, created.id);
+            assert_eq!(stored(&user, "555-0000000-0000006").await.id, upserted.id);
+            let under_other = orders_collection().count_documents(doc! { "user_id": &other }).await.unwrap();
+            assert_eq!(under_other, 0);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn padded_order_numbers_update_the_same_order() {
+        run(async {
+            let user = unique_user();
+            for order_number in ["666-0000000-0000006", " 666-0000000-0000006 "] {
+                let request = create_request(order_number, "uncommented");
+                let (_, Json(order)) = create_order(AuthUser(Claims::for_user(&user)), AppJson(request)).await.unwrap();
+                assert_eq!(order.order_number, "666-0000000-0000006");
+            }
+            // The second write updated the first order instead of adding one
+            let stored = orders_collection().count_documents(doc! { "user_id": &user }).await.unwrap();
+            assert_eq!(stored, 1);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs MongoDB (just db)"]
+    fn tenants_never_see_each_others_orders() {
+        run(async {
+            // The same subject, signed in through two tenants
+            let user = unique_user();
+            let in_tenant = |tenant: &str| Claims { tenant_id: Some(tenant.to_string()), ..Claims::for_user(&user) };
+            let (a, b) = (in_tenant("tenant-a"), in_tenant("tenant-b"));
+            let create = |claims: &Claims, status: &str| {
+                create_order(AuthUser(claims.clone()), AppJson(create_request("333-0000000-0000003", status)))
+            };
+
+            let (_, Json(order_a)) = create(&a, "uncommented").await.unwrap();
+            // Same order number in the other tenant: a second order, not an update of the first
+            let (_, Json(order_b)) = create(&b, "commented").await.unwrap();
+            assert_ne!(order_a.id, order_b.id);
+
+            let found = find_order("test", &order_a.id, &a.owner()).await.unwrap();
+            assert_eq!((found.status, found.tenant_id.as_deref()), (OrderStatus::Uncommented, Some("tenant-a")));
+            assert!(matches!(find_order("test", &order_a.id, &b.owner()).await, Err(AppError::NotFound(_))));
+            assert!(matches!(find_order("test", &order_a.id, &Claims::for_user(&user).owner()).await, Err(AppError::NotFound(_))));
+
+            // Deleting from the other tenant leaves the order alone
+            let request = serde_json::from_value(json!({ "ids": [&order_a.id] })).unwrap();
+            let Json(deleted) = batch_delete_orders(AuthUser(b.clone()), AppJson(request)).await.unwrap();
+            assert_eq!(deleted.deleted, 0);
+            assert!(find_order("test", &order_a.id, &a.owner()).await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use axum::{extract::Path, http::StatusCode, Json};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use mongodb::bson::{self, doc};
+use ring::hmac;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::{AuthError, AuthUser, Owner};
+use crate::config::get_config;
+use crate::db::{self, orders_collection, retry_read};
+use crate::errors::{ApiError, AppError, AppResult};
+use crate::models::{OrderEntity, ShareLink, SharedOrder};
+use crate::status_format::StatusFormatParams;
+
+/// Tracing target for share links (`RUST_LOG=order_wizard::share=debug`)
+const TARGET: &str = "order_wizard::share";
+
+/// Creating and revoking links; needs the owner's bearer token
+pub fn router() -> OpenApiRouter {
+    OpenApiRouter::new().routes(routes!(create_share_link, revoke_share_links))
+}
+
+/// Opening a link; the token is the only credential
+pub fn public_router() -> OpenApiRouter {
+    OpenApiRouter::new().routes(routes!(get_shared_order))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/share",
+    tag = "Orders",
+    summary = "Create a share link",
+    description = "Returns a signed link that shows a read-only view of the order to anyone holding it, without \
+        signing in, until it expires (`SHARE_LINK_TTL_SECS`). Not available unless `SHARE_LINK_SECRET` is configured.",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 201, description = "Share link created", body = ShareLink),
+        (status = 404, description = "Order not found, or sharing is not configured", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn create_share_link(
+    AuthUser(claims): AuthUser,
+    Path(id): Path<String>,
+) -> AppResult<(StatusCode, Json<ShareLink>)> {
+    let config = get_config();
+    let Some(secret) = &config.share_link_secret else {
+        return Err(AppError::not_found("Share link endpoint"));
+    };
+    tracing::info!(target: TARGET, "POST /orders/{}/share - user: {}", id, claims.sub);
+
+    let owner = claims.owner();
+    let collection = orders_collection();
+    let filter = owner.filter(doc! { "id": &id, "deleted_at": null });
+    let order = retry_read("POST /orders/{id}/share", || collection.find_one(filter.clone()).max_time(config.db_max_time))
+        .await
+        .map_err(AppError::database)?
+        .ok_or_else(|| AppError::not_found("Order"))?;
+
+    let expires_at = bson::DateTime::now().timestamp_millis() / 1000 + config.share_link_ttl.as_secs() as i64;
+    let token = sign(secret, &SharePayload {
+        owner,
+        order_id: order.id,
+        expires_at,
+        version: order.share_version.unwrap_or(0),
+    });
+    let path = format!("/orders/shared/{}", token);
+    Ok((
+        StatusCode::CREATED,
+        Json(ShareLink {
+            url: format!("{}{}", config.share_link_base_url.as_deref().unwrap_or_default(), path),
+            expires_at: bson::DateTime::from_millis(expires_at * 1000)
+                .try_to_rfc3339_string()
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+            token,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/orders/{id}/share",
+    tag = "Orders",
+    summary = "Revoke share links",
+    description = "Invalidates every share link created for the order so far; new links can be created afterwards",
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    responses(
+        (status = 204, description = "Existing links revoked"),
+        (status = 404, description = "Order not found", body = ApiError),
+        (status = 401, description = "Unauthorized", body = AuthError)
+    ),
+    security(("bearer_auth" = []))
+)]
+async fn revoke_share_links(AuthUser(claims): AuthUser, Path(id): Path<String>) -> AppResult<StatusCode> {
+    tracing::info!(target: TARGET, "DELETE /orders/{}/share - user: {}", id, claims.sub);
+
+    // Links carry the version they were made for, so bumping it retires all of them
+    let result = orders_collection()
+        .update_one(claims.owner().filter(doc! { "id": &id }), doc! { "$inc": { "share_version": 1 } })
+        .await
+        .map_err(AppError::database)?;
+    if result.matched_count == 0 {
+        return Err(AppError::not_found("Order"));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/orders/shared/{token}",
+    tag = "Orders",
+    summary = "Open a share link",
+    description = "Returns the read-only view of a shared order. Expired, revoked and malformed links, and links to \
+        orders that were since deleted, all answer 404.",
+    params(
+        ("token" = String, Path, description = "Token from POST /orders/{id}/share"),
+        StatusFormatParams
+    ),
+    responses(
+        (status = 200, description = "Shared order", body = SharedOrder),
+        (status = 404, description = "Link is not valid (any more)", body = ApiError)
+    )
+)]
+async fn get_shared_order(Path(token): Path<String>) -> AppResult<Json<SharedOrder>> {
+    let config = get_config();
+    let payload = config
+        .share_link_secret
+        .as_deref()
+        .and_then(|secret| verify(secret, &token))
+        .filter(|payload| payload.expires_at > bson::DateTime::now().timestamp_millis() / 1000)
+        .ok_or_else(|| {
+            tracing::debug!(target: TARGET, "GET /orders/shared - invalid or expired token");
+            AppError::not_found("Shared order")
+        })?;
+
+    let collection = orders_collection();
+    let filter = payload.owner.filter(doc! { "id": &payload.order_id, "deleted_at": null });
+    let order: Option<OrderEntity> = retry_read("GET /orders/shared/{token}", || {
+        collection
+            .find_one(filter.clone())
+            .max_time(config.db_max_time)
+            .selection_criteria(db::read_only())
+    })
+    .await
+    .map_err(AppError::database)?;
+    let order = order
+        .filter(|order| order.share_version.unwrap_or(0) == payload.version)
+        .ok_or_else(|| AppError::not_found("Shared order"))?;
+
+    tracing::info!(target: TARGET, "GET /orders/shared - order: {}", order.id);
+    Ok(Json(SharedOrder::from(order)))
+}
+
+/// What a share token vouches for
+struct SharePayload {
+    owner: Owner,
+    order_id: String,
+    /// Unix seconds
+    expires_at: i64,
+    /// The order's `share_version` when the link was made
+    version: u32,
+}
+
+/// `<payload>.<signature>`, both base64url: the payload is the newline-joined fields, the
+/// signature its HMAC-SHA256 under `SHARE_LINK_SECRET`. The tenant comes last and only
+/// when there is one, so single-tenant tokens keep their original four fields.
+fn sign(secret: &str, payload: &SharePayload) -> String {
+    let mut fields = vec![
+        payload.owner.user_id.clone(),
+        payload.order_id.clone(),
+        payload.expires_at.to_string(),
+        payload.version.to_string(),
+    ];
+    fields.extend(payload.owner.tenant_id.clone());
+    let fields = fields.join("\n");
+    let encoded = URL_SAFE_NO_PAD.encode(fields);
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&key, encoded.as_bytes()));
+    format!("{}.{}", encoded, signature)
+}
+
+fn verify(secret: &str, token: &str) -> Option<SharePayload> {
+    let (encoded, signature) = token.split_once('.')?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, encoded.as_bytes(), &URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+
+    let fields = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded).ok()?).ok()?;
+    let mut fields = fields.split('\n');
+    let user_id = fields.next()?.to_string();
+    let order_id = fields.next()?.to_string();
+    let expires_at = fields.next()?.parse().ok()?;
+    let version = fields.next()?.parse().ok()?;
+    let tenant_id = fields.next().map(str::to_string);
+    let payload = SharePayload {
+        owner: Owner { user_id, tenant_id },
+        order_id,
+        expires_at,
+        version,
+    };
+    fields.next().is_none().then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(tenant_id: Option<&str>) -> SharePayload {
+        SharePayload {
+            owner: Owner { user_id: "user-1".into(), tenant_id: tenant_id.map(String::from) },
+            order_id: "order-1".into(),
+            expires_at: 1_900_000_000,
+            version: 2,
+        }
+    }
+
+    #[test]
+    fn tokens_carry_the_owners_tenant() {
+        let token = sign("secret", &payload(Some("tenant-a")));
+        let verified = verify("secret", &token).unwrap();
+        assert_eq!(verified.owner, payload(Some("tenant-a")).owner);
+        assert_eq!((verified.order_id.as_str(), verified.expires_at, verified.version), ("order-1", 1_900_000_000, 2));
+    }
+
+    #[test]
+    fn single_tenant_tokens_keep_four_fields() {
+        let token = sign("secret", &payload(None));
+        let (encoded, _) = token.split_once('.').unwrap();
+        let fields = String::from_utf8(URL_SAFE_NO_PAD.decode(encoded).unwrap()).unwrap();
+        assert_eq!(fields.split('\n').count(), 4);
+        assert_eq!(verify("secret", &token).unwrap().owner, payload(None).owner);
+    }
+
+    #[test]
+    fn tampered_or_foreign_tokens_are_refused() {
+        let token = sign("secret", &payload(Some("tenant-a")));
+        assert!(verify("other-secret", &token).is_none());
+
+        // Swapping in another tenant's payload breaks the signature
+        let forged = sign("secret", &payload(Some("tenant-b")));
+        let (_, signature) = token.split_once('.').unwrap();
+        let (forged_payload, _) = forged.split_once('.').unwrap();
+        assert!(verify("secret", &format!("{}.{}", forged_payload, signature)).is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use axum::{extract::DefaultBodyLimit, http::StatusCode};
+use tower_governor::{governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor, GovernorLayer};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::auth::OptionalAuthUser;
+use crate::errors::{ApiError, AppResult};
+use crate::extract::AppJson;
+use crate::models::{TelemetryEvent, TelemetryLevel};
+use crate::validation::Validate;
+
+/// Tracing target for reported client events (`RUST_LOG=order_wizard::telemetry=info`)
+const TARGET: &str = "order_wizard::telemetry";
+
+/// Telemetry bodies are small; anything larger is rejected before parsing
+const MAX_TELEMETRY_BYTES: usize = 8 * 1024;
+
+pub fn router() -> OpenApiRouter {
+    // Tighter than the global limit: 10 events per minute per IP, bursts of 10
+    let governor_config = GovernorConfigBuilder::default()
+        .per_second(6)
+        .burst_size(10)
+        .key_extractor(SmartIpKeyExtractor)
+        .finish()
+        .expect("Failed to create telemetry rate limiter config");
+
+    OpenApiRouter::new()
+        .routes(routes!(report_telemetry))
+        .layer(DefaultBodyLimit::max(MAX_TELEMETRY_BYTES))
+        .layer(GovernorLayer::new(governor_config))
+}
+
+#[utoipa::path(
+    post,
+    path = "/telemetry",
+    tag = "Telemetry",
+    summary = "Report a client-side event",
+    description = "Logs a diagnostic event from the extension or web app. A bearer token is optional; \
+        when valid, the event is tagged with that user.",
+    request_body = TelemetryEvent,
+    responses(
+        (status = 204, description = "Event recorded"),
+        (status = 400, description = "Validation failed", body = ApiError),
+        (status = 413, description = "Event too large", body = ApiError),
+        (status = 415, description = "Body is not application/json", body = ApiError),
+        (status = 429, description = "Too many events")
+    ),
+    security((), ("bearer_auth" = []))
+)]
+async fn report_telemetry(
+    OptionalAuthUser(claims): OptionalAuthUser,
+    AppJson(event): AppJson<TelemetryEvent>,
+) -> AppResult<StatusCode> {
+    event.validate()?;
+
+    let user = claims.as_ref().map_or("anonymous", |c| c.sub.as_str());
+    let context = event.context.map(|c| c.to_string()).unwrap_or_default();
+    match event.level {
+        TelemetryLevel::Debug => tracing::debug!(target: TARGET, "user: {} - {} {}", user, event.message, context),
+        TelemetryLevel::Info => tracing::info!(target: TARGET, "user: {} - {} {}", user, event.message, context),
+        TelemetryLevel::Warn => tracing::warn!(target: TARGET, "user: {} - {} {}", user, event.message, context),
+        TelemetryLevel::Error => tracing::error!(target: TARGET, "user: {} - {} {}", user, event.message, context),
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
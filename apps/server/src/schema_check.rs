@@ -0,0 +1,207 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::sync::OnceLock;
+
+use crate::errors::AppError;
+
+/// Tracing target for schema drift warnings (`RUST_LOG=order_wizard::schema_check=debug`)
+const TARGET: &str = "order_wizard::schema_check";
+
+/// Generated OpenAPI document, as JSON, that request bodies are checked against
+static SPEC: OnceLock<Value> = OnceLock::new();
+
+/// Bodies declared larger than this are passed through unchecked; an undeclared body that
+/// turns out larger is refused, since it has been consumed by then
+const MAX_CHECKED_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+pub fn init(api: &utoipa::openapi::OpenApi) {
+    let spec = serde_json::to_value(api).expect("OpenAPI document serializes to JSON");
+    SPEC.set(spec).expect("Schema check already initialized");
+}
+
+/// Debug-only middleware that logs where a JSON request body disagrees with the
+/// OpenAPI request schema (missing required fields, unknown fields, wrong types).
+/// Requests are not rejected for drift; this only surfaces it between the utoipa
+/// annotations and what clients actually send.
+pub async fn check_request_schema(request: Request, next: Next) -> Response {
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let is_mutating = matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH);
+    let is_oversized = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|length| length > MAX_CHECKED_BODY_BYTES);
+    let Some(spec) = SPEC.get().filter(|_| is_json && is_mutating && !is_oversized) else {
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_CHECKED_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            // The body is gone, so forwarding the request would hand the handler an empty one
+            tracing::warn!(target: TARGET, "schema check: could not buffer {} {}: {}", parts.method, parts.uri.path(), e);
+            return AppError::payload_too_large(format!("Request body exceeds {} bytes", MAX_CHECKED_BODY_BYTES))
+                .into_response();
+        }
+    };
+
+    let operation = format!("{} {}", parts.method, parts.uri.path());
+    match (request_schema(spec, &parts.method, parts.uri.path()), serde_json::from_slice::<Value>(&bytes)) {
+        (Some(schema), Ok(body)) => {
+            let mut problems = Vec::new();
+            check(spec, schema, &body, "$", &mut problems);
+            for problem in problems {
+                tracing::warn!(target: TARGET, "schema check: {}: {}", operation, problem);
+            }
+        }
+        (None, _) => tracing::debug!(target: TARGET, "schema check: {} has no documented JSON request body", operation),
+        (_, Err(_)) => {} // Malformed JSON is reported by the Json extractor itself
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+/// Find the `application/json` request body schema for a concrete request path
+fn request_schema<'a>(spec: &'a Value, method: &Method, path: &str) -> Option<&'a Value> {
+    let method = method.as_str().to_ascii_lowercase();
+    spec["paths"]
+        .as_object()?
+        .iter()
+        .filter(|(template, _)| path_matches(template, path))
+        .find_map(|(_, item)| item.get(&method))
+        .and_then(|op| op.pointer("/requestBody/content/application~1json/schema"))
+}
+
+fn path_matches(template: &str, path: &str) -> bool {
+    let template: Vec<&str> = template.trim_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+    template.len() == path.len()
+        && template
+            .iter()
+            .zip(&path)
+            .all(|(t, p)| (t.starts_with('{') && t.ends_with('}')) || t == p)
+}
+
+fn resolve<'a>(spec: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema.get("$ref").and_then(Value::as_str) {
+        Some(location) => location
+            .strip_prefix('#')
+            .and_then(|pointer| spec.pointer(pointer))
+            .unwrap_or(&Value::Null),
+        None => schema,
+    }
+}
+
+fn check(spec: &Value, schema: &Value, value: &Value, at: &str, problems: &mut Vec<String>) {
+    let schema = resolve(spec, schema);
+
+    if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+        for part in all_of {
+            check(spec, part, value, at, problems);
+        }
+        return;
+    }
+    if let Some(variants) = schema
+        .get("oneOf")
+        .or_else(|| schema.get("anyOf"))
+        .and_then(Value::as_array)
+    {
+        let matches_any = variants.iter().any(|variant| {
+            let mut scratch = Vec::new();
+            check(spec, variant, value, at, &mut scratch);
+            scratch.is_empty()
+        });
+        if !matches_any {
+            problems.push(format!("{} matches none of the allowed schemas", at));
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(t, value)) {
+            problems.push(format!("{} should be {} but is {}", at, allowed.join(" | "), json_type(value)));
+            return;
+        }
+    }
+
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        if !value.is_null() && !values.contains(value) {
+            problems.push(format!("{} is not one of the documented values", at));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for required in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !fields.contains_key(required) {
+                    problems.push(format!("{}.{} is required but missing", at, required));
+                }
+            }
+            if let Some(properties) = properties {
+                for (name, field) in fields {
+                    match properties.get(name) {
+                        Some(field_schema) => {
+                            check(spec, field_schema, field, &format!("{}.{}", at, name), problems)
+                        }
+                        None => problems.push(format!("{}.{} is not documented", at, name)),
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items").filter(|s| s.is_object()) {
+                for (i, item) in items.iter().enumerate() {
+                    check(spec, item_schema, item, &format!("{}[{}]", at, i), problems);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
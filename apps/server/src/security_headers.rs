@@ -0,0 +1,16 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::config::get_config;
+
+/// Adds the configured hardening headers (`X-Content-Type-Options`, `X-Frame-Options`,
+/// `Referrer-Policy`, `Content-Security-Policy`) unless the handler already set them
+pub async fn security_headers(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    for (name, value) in &get_config().security_headers {
+        if !headers.contains_key(name) {
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+    response
+}
@@ -0,0 +1,142 @@
+use axum::{
+    extract::{Query, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize, Serializer};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::errors::AppError;
+use crate::models::OrderStatus;
+
+/// How `status` is written in order responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusFormat {
+    /// `"uncommented"`, `"commented"`, ... (default)
+    #[default]
+    String,
+    /// The stable integer code from `OrderStatus::code`, for bandwidth-sensitive clients
+    Code,
+}
+
+/// Status representation parameter accepted by every order endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct StatusFormatParams {
+    /// `code` writes `status` as an integer: 0 uncommented, 1 commented,
+    /// 2 comment_revealed, 3 reimbursed. Defaults to `string`.
+    pub status_format: Option<StatusFormat>,
+}
+
+tokio::task_local! {
+    /// Format requested by the request being answered
+    static FORMAT: StatusFormat;
+}
+
+/// The format for the current request; `String` outside one (webhooks, background jobs)
+pub fn current() -> StatusFormat {
+    FORMAT.try_with(|format| *format).unwrap_or_default()
+}
+
+/// Serialize with `format` in effect, for bodies written after the handler returned
+/// (streamed lists)
+pub fn scoped<T>(format: StatusFormat, serialize: impl FnOnce() -> T) -> T {
+    FORMAT.sync_scope(format, serialize)
+}
+
+/// `serialize_with` for `status` fields in responses
+pub fn serialize<S: Serializer>(status: &OrderStatus, serializer: S) -> Result<S::Ok, S::Error> {
+    match current() {
+        StatusFormat::Code => serializer.serialize_u8(status.code()),
+        StatusFormat::String => status.serialize(serializer),
+    }
+}
+
+/// Read `statusFormat` from the query and answer the request with it in effect. The
+/// parameter is part of the query string, so cached lists keep the two forms apart.
+pub async fn apply(request: Request, next: Next) -> Response {
+    let format = match Query::<StatusFormatParams>::try_from_uri(request.uri()) {
+        Ok(Query(params)) => params.status_format.unwrap_or_default(),
+        Err(e) => return AppError::bad_request(e.body_text()).into_response(),
+    };
+    FORMAT.scope(format, next.run(request)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Order, OrderEntity};
+    use serde_json::json;
+
+    const ALL: [OrderStatus; 4] = [
+        OrderStatus::Uncommented,
+        OrderStatus::Commented,
+        OrderStatus::CommentRevealed,
+        OrderStatus::Reimbursed,
+    ];
+
+    fn order(status: &str) -> Order {
+        let entity: OrderEntity = serde_json::from_value(json!({
+            "id": "order-1",
+            "user_id": "user-1",
+            "order_number": "123-4567890-1234567",
+            "product_name": "Headphones",
+            "order_date": "December 25, 2024",
+            "product_image": "",
+            "price": "$29.99",
+            "status": status,
+        }))
+        .unwrap();
+        Order::from(entity)
+    }
+
+    #[test]
+    fn codes_are_stable() {
+        let codes: Vec<u8> = ALL.iter().map(OrderStatus::code).collect();
+        assert_eq!(codes, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn string_format_round_trips() {
+        for status in ALL {
+            let json = serde_json::to_value(order(status.as_str())).unwrap();
+            assert_eq!(json["status"], status.as_str());
+            let parsed: OrderStatus = serde_json::from_value(json["status"].clone()).unwrap();
+            assert_eq!(parsed, status);
+        }
+    }
+
+    #[test]
+    fn code_format_writes_the_code() {
+        for status in ALL {
+            let json = scoped(StatusFormat::Code, || serde_json::to_value(order(status.as_str()))).unwrap();
+            assert_eq!(json["status"], status.code());
+            let decoded = ALL.iter().find(|s| u64::from(s.code()) == json["status"].as_u64().unwrap());
+            assert_eq!(decoded, Some(&status));
+        }
+    }
+
+    #[test]
+    fn outside_a_request_the_string_form_is_used() {
+        assert_eq!(current(), StatusFormat::String);
+    }
+
+    #[test]
+    fn etag_does_not_depend_on_the_format() {
+        let order = order("commented");
+        assert_eq!(order.etag(), scoped(StatusFormat::Code, || order.etag()));
+    }
+
+    #[test]
+    fn stored_entities_keep_the_string_status() {
+        let entity: OrderEntity = serde_json::from_value(json!({
+            "id": "order-1", "user_id": "user-1", "order_number": "1", "product_name": "p",
+            "order_date": "d", "product_image": "", "price": "1", "status": "commented",
+        }))
+        .unwrap();
+        let stored = scoped(StatusFormat::Code, || mongodb::bson::to_document(&entity)).unwrap();
+        assert_eq!(stored.get_str("status"), Ok("commented"));
+    }
+}
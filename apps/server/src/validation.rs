@@ -0,0 +1,382 @@
+use mongodb::bson::Document;
+use regex::Regex;
+use std::{collections::HashMap, str::FromStr};
+
+use crate::config::get_config;
+use crate::errors::{AppError, AppResult, FieldErrors};
+use crate::models::{
+    normalize_tags, BatchDeleteRequest, BatchGetRequest, BatchUpsertRequest, CreateOrderRequest, ListOrdersQuery, OrderEntity, OrderShape, RekeyOrderRequest, TelemetryEvent, UpdateOrderRequest, UpsertOrderRequest,
+};
+
+/// What happens to an order number that does not match its source's pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// Patterns are not checked
+    Off,
+    /// Logged under `order_wizard::validation`, but the order is stored
+    Warn,
+    /// Rejected with a validation error
+    Reject,
+}
+
+impl FromStr for Strictness {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Strictness::Off),
+            "warn" => Ok(Strictness::Warn),
+            "reject" => Ok(Strictness::Reject),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Expected order-number shape per marketplace (`source`); sources without a pattern
+/// are not checked
+#[derive(Debug)]
+pub struct OrderNumberFormats {
+    pub strictness: Strictness,
+    /// For orders sent without a `source`
+    pub unsourced: Option<Regex>,
+    pub by_source: HashMap<String, Regex>,
+}
+
+impl OrderNumberFormats {
+    fn pattern(&self, source: Option<&str>) -> Option<&Regex> {
+        match source {
+            Some(source) => self.by_source.get(source),
+            None => self.unsourced.as_ref(),
+        }
+    }
+
+    /// Match an order number against the pattern for its source
+    pub fn check(&self, errors: &mut ValidationErrors, path: &str, source: Option<&str>, order_number: &str) {
+        if self.strictness == Strictness::Off || order_number.trim().is_empty() {
+            return;
+        }
+        let Some(pattern) = self.pattern(source) else {
+            return;
+        };
+        if pattern.is_match(order_number) {
+            return;
+        }
+        match self.strictness {
+            Strictness::Reject => errors.add(
+                field(path, "orderNumber"),
+                format!("does not match the {} order number format", source.unwrap_or("default")),
+            ),
+            _ => tracing::warn!(target: TARGET, "Order number {:?} does not match the {} format", order_number, source.unwrap_or("default")),
+        }
+    }
+}
+
+/// Tracing target for order numbers let through in `warn` mode
+const TARGET: &str = "order_wizard::validation";
+
+/// Collects every validation problem so a request is rejected with all of them at once
+#[derive(Debug, Default)]
+pub struct ValidationErrors(FieldErrors);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.entry(field.into()).or_default().push(message.into());
+    }
+
+    pub fn into_result(self) -> AppResult<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(self.0))
+        }
+    }
+}
+
+/// Request bodies that check their own fields; `path` prefixes field names for nested items
+pub trait Validate {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors);
+
+    fn validate(&self) -> AppResult<()> {
+        let mut errors = ValidationErrors::default();
+        self.validate_into("", &mut errors);
+        errors.into_result()
+    }
+}
+
+fn field(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn require_non_empty(errors: &mut ValidationErrors, path: &str, name: &str, value: &str) {
+    if value.trim().is_empty() {
+        errors.add(field(path, name), "must not be empty");
+    }
+}
+
+/// Match an order number against the pattern for its source (`ORDER_NUMBER_PATTERNS`)
+pub fn check_order_number(errors: &mut ValidationErrors, path: &str, source: Option<&str>, order_number: &str) {
+    get_config().order_number_formats.check(errors, path, source, order_number);
+}
+
+fn check_note(errors: &mut ValidationErrors, path: &str, note: Option<&String>) {
+    let max = get_config().max_note_length;
+    if note.is_some_and(|n| n.chars().count() > max) {
+        errors.add(field(path, "note"), format!("must be at most {} characters", max));
+    }
+}
+
+fn check_tags(errors: &mut ValidationErrors, path: &str, tags: &[String]) {
+    let config = get_config();
+    let tags = normalize_tags(tags);
+    if tags.len() > config.max_tags_per_order {
+        errors.add(field(path, "tags"), format!("must contain at most {} tags", config.max_tags_per_order));
+    }
+    if tags.iter().any(|t| t.chars().count() > config.max_tag_length) {
+        errors.add(field(path, "tags"), format!("tags must be at most {} characters", config.max_tag_length));
+    }
+}
+
+impl Validate for CreateOrderRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        require_non_empty(errors, path, "id", &self.id);
+        require_non_empty(errors, path, "orderNumber", &self.order_number);
+        check_order_number(errors, path, self.source.as_deref(), &self.order_number);
+        if let Some(source) = &self.source {
+            require_non_empty(errors, path, "source", source);
+        }
+        require_non_empty(errors, path, "productName", &self.product_name);
+        check_note(errors, path, self.note.as_ref());
+        check_tags(errors, path, &self.tags);
+    }
+}
+
+impl Validate for UpsertOrderRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        if let Some(id) = &self.id {
+            require_non_empty(errors, path, "id", id);
+        }
+        if let Some(source) = &self.source {
+            require_non_empty(errors, path, "source", source);
+        }
+        require_non_empty(errors, path, "productName", &self.product_name);
+        check_note(errors, path, self.note.as_ref());
+        check_tags(errors, path, &self.tags);
+    }
+}
+
+impl Validate for UpdateOrderRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        check_note(errors, path, self.note.as_ref());
+        if let Some(tags) = &self.tags {
+            check_tags(errors, path, tags);
+        }
+    }
+}
+
+impl Validate for RekeyOrderRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        require_non_empty(errors, path, "newId", &self.new_id);
+    }
+}
+
+/// Parameter combinations of `GET /orders` that contradict each other. Delta sync
+/// (`updatedSince`) must see every changed order, so it cannot be narrowed by filters
+/// that hide orders which changed out of the filtered set, nor exclude archived ones.
+impl Validate for ListOrdersQuery {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        if self.updated_since.is_none() {
+            return;
+        }
+        for (name, set) in [("from", self.from.is_some()), ("to", self.to.is_some()), ("tag", !self.tag.is_empty())] {
+            if set {
+                errors.add(
+                    field(path, name),
+                    "cannot be combined with `updatedSince`; delta sync would miss orders that changed out of the filter",
+                );
+            }
+        }
+        if self.include_archived == Some(false) {
+            errors.add(
+                field(path, "includeArchived"),
+                "cannot be false with `updatedSince`; delta sync would miss orders that were archived",
+            );
+        }
+        if self.shape == Some(OrderShape::Nested) {
+            errors.add(
+                field(path, "shape"),
+                "cannot be nested with `updatedSince`; the nested shape has no `deletedAt`, so delta sync would keep deleted orders",
+            );
+        }
+    }
+}
+
+/// Reject a PATCH whose `changes` (from [`UpdateOrderRequest::changes_from`]) touch fields
+/// the order's current status locks
+pub fn check_editable(changes: &Document, current: &OrderEntity) -> AppResult<()> {
+    let mut errors = ValidationErrors::default();
+    let editable = current.status.editable_fields();
+    for name in ["status", "note", "tags"] {
+        if changes.contains_key(name) && !editable.contains(&name) {
+            errors.add(name, format!("cannot be changed while the order is {}", current.status.as_str()));
+        }
+    }
+    errors.into_result()
+}
+
+/// Reject a batch whose array exceeds `MAX_BATCH_SIZE`; `false` when it was too large,
+/// so callers can skip checking its items
+fn check_batch_size(errors: &mut ValidationErrors, path: &str, name: &str, len: usize, items: &str) -> bool {
+    let max = get_config().max_batch_size;
+    if len > max {
+        errors.add(field(path, name), format!("must contain at most {} {}", max, items));
+        return false;
+    }
+    true
+}
+
+impl Validate for BatchUpsertRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        if !check_batch_size(errors, path, "orders", self.orders.len(), "orders") {
+            return;
+        }
+        for (i, order) in self.orders.iter().enumerate() {
+            order.validate_into(&field(path, &format!("orders[{}]", i)), errors);
+        }
+    }
+}
+
+impl Validate for BatchGetRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        check_batch_size(errors, path, "ids", self.ids.len(), "ids");
+    }
+}
+
+impl Validate for BatchDeleteRequest {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        check_batch_size(errors, path, "ids", self.ids.len(), "ids");
+    }
+}
+
+/// Longest telemetry message accepted, in characters
+pub const MAX_TELEMETRY_MESSAGE_LENGTH: usize = 2000;
+
+impl Validate for TelemetryEvent {
+    fn validate_into(&self, path: &str, errors: &mut ValidationErrors) {
+        require_non_empty(errors, path, "message", &self.message);
+        if self.message.chars().count() > MAX_TELEMETRY_MESSAGE_LENGTH {
+            errors.add(
+                field(path, "message"),
+                format!("must be at most {} characters", MAX_TELEMETRY_MESSAGE_LENGTH),
+            );
+        }
+        if self.context.as_ref().is_some_and(|c| !c.is_object()) {
+            errors.add(field(path, "context"), "must be a JSON object");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Field names `request` is rejected for, or none when it is valid
+    fn invalid_fields(request: &impl Validate) -> Vec<String> {
+        crate::config::init_test_config();
+        match request.validate() {
+            Ok(()) => Vec::new(),
+            Err(AppError::Validation(fields)) => fields.into_keys().collect(),
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    fn list_query(query: serde_json::Value) -> ListOrdersQuery {
+        serde_json::from_value(query).unwrap()
+    }
+
+    #[test]
+    fn delta_sync_needs_the_flat_shape() {
+        let since = "2024-12-25T00:00:00Z";
+        assert_eq!(invalid_fields(&list_query(json!({ "updatedSince": since, "shape": "nested" }))), ["shape"]);
+        assert_eq!(invalid_fields(&list_query(json!({ "updatedSince": since, "shape": "flat" }))), Vec::<String>::new());
+        assert_eq!(invalid_fields(&list_query(json!({ "updatedSince": since }))), Vec::<String>::new());
+        assert_eq!(invalid_fields(&list_query(json!({ "shape": "nested" }))), Vec::<String>::new());
+    }
+
+    fn ids(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("order-{}", i)).collect()
+    }
+
+    #[test]
+    fn batches_may_hold_up_to_the_maximum() {
+        crate::config::init_test_config();
+        let max = get_config().max_batch_size;
+
+        let upsert = BatchUpsertRequest { orders: (0..max).map(|_| CreateOrderRequest::fixture(json!({}))).collect() };
+        assert_eq!(invalid_fields(&upsert), Vec::<String>::new());
+        assert_eq!(invalid_fields(&BatchGetRequest { ids: ids(max) }), Vec::<String>::new());
+        assert_eq!(invalid_fields(&BatchDeleteRequest { ids: ids(max) }), Vec::<String>::new());
+    }
+
+    #[test]
+    fn batches_over_the_maximum_are_refused() {
+        crate::config::init_test_config();
+        let max = get_config().max_batch_size;
+
+        // Items of an oversized batch are not checked one by one
+        let mut orders: Vec<_> = (0..=max).map(|_| CreateOrderRequest::fixture(json!({}))).collect();
+        orders[0].product_name.clear();
+        assert_eq!(invalid_fields(&BatchUpsertRequest { orders }), ["orders"]);
+        assert_eq!(invalid_fields(&BatchGetRequest { ids: ids(max + 1) }), ["ids"]);
+        assert_eq!(invalid_fields(&BatchDeleteRequest { ids: ids(max + 1) }), ["ids"]);
+    }
+
+    fn with_tags(tags: Vec<String>) -> CreateOrderRequest {
+        CreateOrderRequest { tags, ..CreateOrderRequest::fixture(json!({})) }
+    }
+
+    #[test]
+    fn tags_may_reach_the_count_and_length_limits() {
+        crate::config::init_test_config();
+        let config = get_config();
+        let tags = (0..config.max_tags_per_order).map(|i| format!("{:0>1$}", i, config.max_tag_length)).collect();
+        assert_eq!(invalid_fields(&with_tags(tags)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn one_tag_too_many_or_one_character_too_long_is_refused() {
+        crate::config::init_test_config();
+        let config = get_config();
+
+        let tags = (0..=config.max_tags_per_order).map(|i| format!("tag-{}", i)).collect();
+        assert_eq!(invalid_fields(&with_tags(tags)), ["tags"]);
+        assert_eq!(invalid_fields(&with_tags(vec!["x".repeat(config.max_tag_length + 1)])), ["tags"]);
+        // Length counts characters, not bytes
+        assert_eq!(invalid_fields(&with_tags(vec!["é".repeat(config.max_tag_length)])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn tag_limits_apply_after_normalization() {
+        crate::config::init_test_config();
+        let config = get_config();
+
+        // Duplicates (after trimming and lowercasing) and blanks do not count
+        let mut tags: Vec<String> = (0..config.max_tags_per_order).map(|i| format!("tag-{}", i)).collect();
+        tags.extend(["TAG-0".to_string(), " tag-1 ".to_string(), "  ".to_string()]);
+        assert_eq!(invalid_fields(&with_tags(tags)), Vec::<String>::new());
+        // Surrounding whitespace does not count towards the length
+        let padded = format!("  {}  ", "x".repeat(config.max_tag_length));
+        assert_eq!(invalid_fields(&with_tags(vec![padded])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn batch_items_are_named_by_position() {
+        let mut orders = vec![CreateOrderRequest::fixture(json!({})), CreateOrderRequest::fixture(json!({}))];
+        orders[1].product_name.clear();
+        assert_eq!(invalid_fields(&BatchUpsertRequest { orders }), ["orders[1].productName"]);
+    }
+}
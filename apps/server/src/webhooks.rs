@@ -0,0 +1,282 @@
+use reqwest::header::CONTENT_TYPE;
+use ring::hmac;
+use serde::Serialize;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+use crate::auth::Owner;
+use crate::config::get_config;
+use crate::dates::now_rfc3339;
+use crate::models::Order;
+
+/// Tracing target for webhook delivery (`RUST_LOG=order_wizard::webhooks=debug`)
+const TARGET: &str = "order_wizard::webhooks";
+
+/// Events waiting for delivery to one endpoint; once full, new events are dropped for it
+/// rather than slowing down the request that produced them
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Attempts per endpoint and event, with the delay doubling after each failure
+const MAX_ATTEMPTS: u32 = 4;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How long one delivery attempt may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `sha256=<hex HMAC-SHA256 of the body>` keyed with `WEBHOOK_SECRET`
+const SIGNATURE_HEADER: &str = "x-ow-signature";
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum OrderEventType {
+    #[serde(rename = "order.created")]
+    Created,
+    #[serde(rename = "order.updated")]
+    Updated,
+    #[serde(rename = "order.deleted")]
+    Deleted,
+}
+
+/// Body POSTed to every webhook endpoint
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderEvent {
+    event: OrderEventType,
+    user_id: String,
+    /// The user's tenant under `MULTI_TENANT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant_id: Option<String>,
+    order_id: String,
+    /// The order after the change; omitted for deletions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<Order>,
+    occurred_at: String,
+}
+
+/// One event, serialized and signed once for every endpoint
+#[derive(Debug)]
+struct Delivery {
+    event: OrderEventType,
+    order_id: String,
+    body: Vec<u8>,
+    signature: String,
+}
+
+/// Signing key and one queue per endpoint, each drained by its own worker so a slow
+/// endpoint only delays its own events
+struct Outbox {
+    key: hmac::Key,
+    queues: Vec<(String, mpsc::Sender<Arc<Delivery>>)>,
+}
+
+static OUTBOX: OnceLock<Outbox> = OnceLock::new();
+
+/// Start the delivery workers when `WEBHOOK_URLS` is set
+pub fn spawn() {
+    let config = get_config();
+    if config.webhook_urls.is_empty() {
+        return;
+    }
+    let secret = config.webhook_secret.as_deref().expect("WEBHOOK_SECRET is checked at startup");
+    if OUTBOX.set(Outbox::start(&config.webhook_urls, secret)).is_err() {
+        panic!("Webhook queues already initialized");
+    }
+}
+
+/// Whether order events are being sent, so routes can fetch the order they report
+pub fn enabled() -> bool {
+    OUTBOX.get().is_some()
+}
+
+/// Queue an event for an order that was written; a no-op while webhooks are off
+pub fn order_changed(event: OrderEventType, owner: &Owner, order: Order) {
+    emit(event, owner, order.id.clone(), Some(order));
+}
+
+/// Queue an event naming an order without its contents (deletions)
+pub fn order_event(event: OrderEventType, owner: &Owner, order_id: &str) {
+    emit(event, owner, order_id.to_string(), None);
+}
+
+fn emit(event: OrderEventType, owner: &Owner, order_id: String, order: Option<Order>) {
+    let Some(outbox) = OUTBOX.get() else {
+        return;
+    };
+    outbox.send(OrderEvent {
+        event,
+        user_id: owner.user_id.clone(),
+        tenant_id: owner.tenant_id.clone(),
+        order_id,
+        order,
+        occurred_at: now_rfc3339(),
+    });
+}
+
+impl Outbox {
+    fn start(urls: &[String], secret: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        let queues = urls
+            .iter()
+            .map(|url| {
+                let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+                tokio::spawn(deliver_all(client.clone(), url.clone(), receiver));
+                (url.clone(), sender)
+            })
+            .collect();
+        Outbox { key: hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()), queues }
+    }
+
+    fn send(&self, event: OrderEvent) {
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!(target: TARGET, "Could not serialize webhook event: {}", e);
+                return;
+            }
+        };
+        let tag = hmac::sign(&self.key, &body);
+        let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        let delivery = Arc::new(Delivery {
+            event: event.event,
+            order_id: event.order_id,
+            body,
+            signature: format!("sha256={}", hex),
+        });
+
+        for (url, queue) in &self.queues {
+            if queue.try_send(delivery.clone()).is_err() {
+                tracing::warn!(target: TARGET, "Webhook queue for {} full, dropping event: {:?}", url, delivery.event);
+            }
+        }
+    }
+}
+
+/// Deliver one endpoint's queued events in order
+async fn deliver_all(client: reqwest::Client, url: String, mut receiver: mpsc::Receiver<Arc<Delivery>>) {
+    while let Some(delivery) = receiver.recv().await {
+        deliver(&client, &url, &delivery).await;
+    }
+}
+
+/// POST one event, retrying network errors, 5xx and 429 with exponential backoff
+async fn deliver(client: &reqwest::Client, url: &str, delivery: &Delivery) {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &delivery.signature)
+            .body(delivery.body.clone())
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                tracing::debug!(target: TARGET, "Delivered {:?} for order {} to {}", delivery.event, delivery.order_id, url);
+                return;
+            }
+            Ok(response) => {
+                let status = response.status();
+                tracing::debug!(target: TARGET, "{} answered {} (attempt {})", url, status, attempt);
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    break;
+                }
+            }
+            Err(e) => tracing::debug!(target: TARGET, "{} failed: {} (attempt {})", url, e, attempt),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    tracing::warn!(target: TARGET, "Gave up delivering {:?} for order {} to {}", delivery.event, delivery.order_id, url);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// An endpoint that accepts connections and never answers
+    async fn hanging_endpoint() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        url
+    }
+
+    /// An endpoint that answers 200 and reports each request it receives
+    async fn answering_endpoint() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                while !complete(&request) {
+                    let mut chunk = [0; 4096];
+                    let read = socket.read(&mut chunk).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&chunk[..read]);
+                }
+                sender.send(String::from_utf8_lossy(&request).into_owned()).await.unwrap();
+                socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    /// Whether `request` holds the headers and the whole `content-length` body
+    fn complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request).to_ascii_lowercase();
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            return false;
+        };
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|length| length.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        body.len() >= length
+    }
+
+    fn event(order_id: &str) -> OrderEvent {
+        OrderEvent {
+            event: OrderEventType::Deleted,
+            user_id: "user".to_string(),
+            tenant_id: None,
+            order_id: order_id.to_string(),
+            order: None,
+            occurred_at: now_rfc3339(),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_slow_endpoint_does_not_hold_up_the_others() {
+        let (url, mut received) = answering_endpoint().await;
+        let outbox = Outbox::start(&[hanging_endpoint().await, url], "secret");
+
+        outbox.send(event("order-1"));
+        outbox.send(event("order-2"));
+        for order_id in ["order-1", "order-2"] {
+            let request = tokio::time::timeout(Duration::from_secs(2), received.recv()).await;
+            let request = request.expect("delivery waited for the hanging endpoint").unwrap();
+            assert!(request.contains(&format!("\"orderId\":\"{}\"", order_id)), "{}", request);
+            assert!(request.to_ascii_lowercase().contains("x-ow-signature: sha256="), "{}", request);
+        }
+    }
+}